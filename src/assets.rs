@@ -10,6 +10,11 @@ pub enum ImgID {
     Aura,
     BackgroundWater,
     BackgroundWave(usize),
+    Beam,
+    Bolt,
+    Bullet,
+    Cannon,
+    Cannonball,
     Card,
     Coin(usize),
     Cursor,
@@ -27,20 +32,26 @@ pub enum ImgID {
     FloorSpawnUp,
     FloorTarget,
     Freeze,
+    FrostTower,
     Hex,
+    Mage,
     NextWave,
     RPM,
     Range,
     RockEdge,
     SellTower,
+    Shard,
     Shop,
     Smoke,
+    Sniper,
     Stone(usize),
+    Storm,
     Take2,
     TileShadow,
     Tree1,
     Tree2,
     Tree3,
+    Volley,
     Walk(Dir),
     Zombie,
 }
@@ -87,6 +98,11 @@ impl Data {
         self.load_img(ctx, BackgroundWave(2), "/sea_wave2.png")?;
         self.load_img(ctx, BackgroundWave(3), "/sea_wave3.png")?;
         self.load_img(ctx, BackgroundWave(4), "/sea_wave4.png")?;
+        self.load_img(ctx, Beam, "/beam.png")?;
+        self.load_img(ctx, Bolt, "/bolt.png")?;
+        self.load_img(ctx, Bullet, "/bullet.png")?;
+        self.load_img(ctx, Cannon, "/cannon.png")?;
+        self.load_img(ctx, Cannonball, "/cannonball.png")?;
         self.load_img(ctx, Card, "/card.png")?;
         self.load_img(ctx, Card, "/card.png")?;
         self.load_img(ctx, Coin(1), "/coin1.png")?;
@@ -115,7 +131,9 @@ impl Data {
         self.load_img(ctx, Walk(Dir::SouthWest), "/tile_walk.png")?;
         self.load_img(ctx, Freeze, "/freeze.png")?;
         self.load_img(ctx, Freeze, "/freeze.png")?;
+        self.load_img(ctx, FrostTower, "/frost_tower.png")?;
         self.load_img(ctx, Hex, "/tile_grass1.png")?;
+        self.load_img(ctx, Mage, "/mage.png")?;
         self.load_img(ctx, NextWave, "/next_wave.png")?;
         self.load_img(ctx, RPM, "/rpm.png")?;
         self.load_img(ctx, RPM, "/rpm.png")?;
@@ -123,12 +141,16 @@ impl Data {
         self.load_img(ctx, Range, "/range.png")?;
         self.load_img(ctx, RockEdge, "/rock_edge.png")?;
         self.load_img(ctx, SellTower, "/sell_tower.png")?;
+        self.load_img(ctx, Shard, "/shard.png")?;
         self.load_img(ctx, Shop, "/shop.png")?;
         self.load_img(ctx, Smoke, "/smoke.png")?;
+        self.load_img(ctx, Sniper, "/sniper.png")?;
         self.load_img(ctx, Stone(1), "/stone1.png")?;
         self.load_img(ctx, Stone(2), "/stone2.png")?;
         self.load_img(ctx, Stone(3), "/stone3.png")?;
         self.load_img(ctx, Stone(4), "/stone4.png")?;
+        self.load_img(ctx, Storm, "/storm.png")?;
+        self.load_img(ctx, Volley, "/volley.png")?;
 
         self.load_img(ctx, BackgroundWave(1), "/sea_wave1.png")?;
         self.load_img(ctx, BackgroundWave(2), "/sea_wave2.png")?;
@@ -5,7 +5,6 @@ use ggez::graphics;
 use ggez::graphics::Color;
 use ggez::{Context, GameResult};
 use rand::prelude::*;
-use rand::thread_rng;
 
 pub struct Wave {
     pos: Point,
@@ -14,17 +13,14 @@ pub struct Wave {
 }
 
 impl Wave {
-    pub fn new() -> Self {
-        let wave_id = 1 + thread_rng().gen::<usize>() % 4;
+    pub fn new<R: Rng>(rng: &mut R) -> Self {
+        let wave_id = 1 + rng.gen::<usize>() % 4;
         let disp = ImgID::BackgroundWave(wave_id);
-        let pos = Point::new(
-            thread_rng().gen::<f32>() * 800.0,
-            thread_rng().gen::<f32>() * 800.0,
-        );
+        let pos = Point::new(rng.gen::<f32>() * 800.0, rng.gen::<f32>() * 800.0);
         return Self {
             disp,
             pos,
-            time: thread_rng().gen::<f32>() * 16.0,
+            time: rng.gen::<f32>() * 16.0,
         };
     }
 
@@ -59,10 +55,10 @@ pub struct Background {
 }
 
 impl Background {
-    pub fn new() -> Self {
+    pub fn new<R: Rng>(rng: &mut R) -> Self {
         let mut waves = vec![];
         for _ in 0..20 {
-            waves.push(Wave::new());
+            waves.push(Wave::new(rng));
         }
         Self {
             waves,
@@ -1,7 +1,7 @@
 use std::collections::HashMap;
 use std::rc::Rc;
 
-#[derive(Eq, PartialEq, Hash, Copy, Clone, Debug, Deserialize)]
+#[derive(Eq, PartialEq, Hash, Copy, Clone, Debug, Serialize, Deserialize)]
 pub enum BuffType {
     Freeze,
     Damage,
@@ -27,6 +27,11 @@ impl Camera {
         return p + self.translate + Vector::new(self.offset.x.floor(), self.offset.y.floor());
     }
 
+    // Inverse of world_pos, turns a position on the screen into world coordinates
+    pub fn map_pos(&self, p: Point) -> Point {
+        return p - self.translate - Vector::new(self.offset.x.floor(), self.offset.y.floor());
+    }
+
     pub fn ground_pos(&self, p: Point) -> Point {
         return p + ((self.translate + self.offset) / 10.0) + Vector::new(180.0, 180.0);
     }
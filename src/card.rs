@@ -1,189 +1,341 @@
 use crate::assets::ImgID;
 use crate::buffs::BuffType;
+use crate::debuffs::Debuff;
+use crate::game_events::GameEvent;
 use crate::gui::CursorMode;
+use crate::level::{level_path, PlayerSettings};
 use crate::map::GameMap;
 use crate::pile_overlay::PileOverlay;
 use crate::playing_state::PlayingState;
 use crate::shop_overlay::ShopOverlay;
 use crate::tower::Tower;
-use crate::wave::WaveStatus;
+use crate::towers::Towers;
+use crate::utils::{invalid_spec, load_specs};
+use crate::wave::Waves;
+use std::collections::HashMap;
 
-#[derive(Eq, PartialEq, Hash, Copy, Clone, Debug)]
-pub enum CardType {
-    Empty,
-    Tower,
+const CARDS_PATH: &str = "resources/rons/cards.ron";
+
+// Cards are identified by the name of their definition in cards.ron
+#[derive(Eq, PartialEq, Hash, Clone, Debug, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct CardType(pub String);
+
+#[derive(Eq, PartialEq, Hash, Copy, Clone, Debug, Serialize, Deserialize)]
+pub enum Targeting {
+    // effects are applied as soon as the card is played
+    Instant,
+    // the player picks a tile on the map first
+    Tile,
+}
+
+#[derive(PartialEq, Clone, Debug, Serialize, Deserialize)]
+pub enum CardEffect {
+    // builds the tower kind of that name from tower.ron
+    BuildTower(String),
     SellTower,
-    DamageEnemy,
-    Shop,
-    Coin(usize),
-    Take2,
+    DamageEnemies { damage: usize, range: f32 },
+    DebuffEnemies { debuff: Debuff, range: f32 },
+    AddGold(usize),
+    DrawCards(usize),
     Buff(BuffType),
-    NextWave,
-    DrawPile,
-    DiscardPile,
+    CallNextWave,
+    OpenShop,
+    ShowDrawPile,
+    ShowDiscardPile,
 }
 
-impl CardType {
-    pub fn get_image_id(&self) -> ImgID {
-        match self {
-            CardType::Empty => ImgID::EmptySlot,
-            CardType::Tower => ImgID::Archer,
-            CardType::SellTower => ImgID::SellTower,
-            CardType::DamageEnemy => ImgID::DamageEnemy,
-            CardType::Shop => ImgID::Shop,
-            CardType::Coin(a) => ImgID::Coin(*a),
-            CardType::Take2 => ImgID::Take2,
-            CardType::Buff(BuffType::Freeze) => ImgID::Freeze,
-            CardType::Buff(BuffType::RPM) => ImgID::RPM,
-            CardType::Buff(BuffType::Range) => ImgID::Range,
-            CardType::Buff(BuffType::Damage) => ImgID::Damage,
-            CardType::Buff(BuffType::Aura) => ImgID::Aura,
-            CardType::NextWave => ImgID::NextWave,
-            CardType::DrawPile => ImgID::DrawPile,
-            CardType::DiscardPile => ImgID::DiscardPile,
+#[derive(Debug, Clone, Deserialize)]
+pub struct CardSpec {
+    pub name: String,
+    pub image: ImgID,
+    pub description: String,
+    pub gold_cost: usize,
+    pub mana_cost: usize,
+    pub aquisition_cost: usize,
+    pub target: Targeting,
+    pub effects: Vec<CardEffect>,
+    #[serde(default)]
+    pub in_shop: bool,
+}
+
+pub struct CardSpecs {
+    pub specs: HashMap<CardType, CardSpec>,
+    // card names in the order of cards.ron, used to lay out the shop
+    pub order: Vec<CardType>,
+}
+
+impl CardSpecs {
+    pub fn new(towers: &Towers) -> Self {
+        let mut specs = HashMap::new();
+        let mut order = vec![];
+        for spec in load_specs::<CardSpec>("cards") {
+            for effect in spec.effects.iter() {
+                if let CardEffect::BuildTower(kind) = effect {
+                    if !towers.catalogue.contains(kind) {
+                        invalid_spec(
+                            CARDS_PATH,
+                            &format!("{} builds unknown tower kind {}", spec.name, kind),
+                        );
+                    }
+                }
+            }
+            let card = CardType::new(&spec.name);
+            order.push(card.clone());
+            specs.insert(card, spec);
         }
+        return Self { specs, order };
     }
 
-    pub fn get_preview_image_id(&self) -> ImgID {
-        return self.get_image_id();
+    // Stops loading the level if player.ron hands out cards cards.ron doesn't have
+    pub fn validate_player(&self, level: &str, settings: &PlayerSettings) {
+        let cards = settings
+            .hand
+            .iter()
+            .chain(&settings.deck)
+            .chain(&settings.actions);
+        for card in cards {
+            if !self.specs.contains_key(card) {
+                invalid_spec(
+                    &level_path(level, "player"),
+                    &format!("unknown card {}", card.0),
+                );
+            }
+        }
+    }
+
+    pub fn get(&self, card: &CardType) -> &CardSpec {
+        return self
+            .specs
+            .get(card)
+            .expect(&format!("Unknown card {}", card.0));
+    }
+
+    pub fn shop(&self) -> Vec<CardType> {
+        return self
+            .order
+            .iter()
+            .filter(|c| self.specs[c].in_shop)
+            .cloned()
+            .collect();
     }
+}
 
-    pub fn get_description(&self) -> &'static str {
+impl CardEffect {
+    // the gold paid for a card with this effect is added to the value of the targeted tower
+    pub fn invests_in_tower(&self) -> bool {
         match self {
-            CardType::Empty => "",
-            CardType::Tower => "Builds a tower",
-            CardType::SellTower => "Destroys a tower",
-            CardType::DamageEnemy => "Damages all enemies in a given range",
-            CardType::Shop => "Buy new cards",
-            CardType::Coin(1) => "Produces 10 Gold",
-            CardType::Coin(2) => "Produces 100 Gold",
-            CardType::Coin(3) => "Produces 1000 Gold",
-            CardType::Coin(_) => unreachable!(),
-            CardType::Take2 => "Draw 2 more cards",
-            CardType::Buff(BuffType::Freeze) => "Slows down enemies",
-            CardType::Buff(BuffType::Range) => "Increases range",
-            CardType::Buff(BuffType::Damage) => "Increases damage",
-            CardType::Buff(BuffType::RPM) => "Increases rpm",
-            CardType::Buff(BuffType::Aura) => "Increases stats of nearby towers",
-            CardType::NextWave => "Immediatly starts next wave",
-            CardType::DrawPile => "Look at your draw pile",
-            CardType::DiscardPile => "Look at you discard pile",
+            CardEffect::BuildTower(_) | CardEffect::Buff(_) => return true,
+            _ => return false,
         }
     }
 
-    pub fn activation_cost_gold(&self, state: &PlayingState) -> usize {
+    pub fn cost_gold(&self, state: &PlayingState) -> usize {
         match self {
-            CardType::Empty => 0,
-            CardType::Tower => state.towers.stats.price,
-            CardType::SellTower => 0,
-            CardType::DamageEnemy => 150,
-            CardType::Shop => 0,
-            CardType::Coin(1) => 0,
-            CardType::Coin(2) => 0,
-            CardType::Coin(3) => 0,
-            CardType::Coin(_) => unreachable!(),
-            CardType::Take2 => 10,
-            CardType::Buff(BuffType::Freeze) => 10,
-            CardType::Buff(BuffType::Damage) => 10,
-            CardType::Buff(BuffType::RPM) => 10,
-            CardType::Buff(BuffType::Range) => 10,
-            CardType::Buff(BuffType::Aura) => 10,
-            CardType::NextWave => 0,
-            CardType::DrawPile => 0,
-            CardType::DiscardPile => 0,
+            CardEffect::BuildTower(kind) => {
+                return state.sim.towers.kind(kind).map_or(0, |t| t.price);
+            }
+            _ => return 0,
         }
     }
 
-    pub fn activation_cost_mana(&self, state: &PlayingState) -> usize {
+    pub fn cost_mana(&self, state: &PlayingState) -> usize {
         match self {
-            CardType::Empty => 0,
-            CardType::Tower => state.towers.stats.mana_cost,
-            CardType::SellTower => 1,
-            CardType::DamageEnemy => 150,
-            CardType::Shop => 0,
-            CardType::Coin(1) => 1,
-            CardType::Coin(2) => 1,
-            CardType::Coin(3) => 1,
-            CardType::Coin(_) => unreachable!(),
-            CardType::Take2 => 2,
-            CardType::Buff(BuffType::Freeze) => 4,
-            CardType::Buff(BuffType::Damage) => 4,
-            CardType::Buff(BuffType::RPM) => 4,
-            CardType::Buff(BuffType::Range) => 4,
-            CardType::Buff(BuffType::Aura) => 4,
-            CardType::NextWave => 0,
-            CardType::DrawPile => 0,
-            CardType::DiscardPile => 0,
+            CardEffect::BuildTower(kind) => {
+                return state.sim.towers.kind(kind).map_or(0, |t| t.mana_cost);
+            }
+            _ => return 0,
         }
     }
 
-    pub fn aquisition_cost(&self, _state: &PlayingState) -> usize {
+    pub fn is_applicable(&self, state: &PlayingState, x: usize, y: usize) -> bool {
         match self {
-            CardType::Empty => 0,
-            CardType::Tower => 60,
-            CardType::SellTower => 50,
-            CardType::DamageEnemy => 100,
-            CardType::Shop => 100,
-            CardType::Coin(1) => 50,
-            CardType::Coin(2) => 500,
-            CardType::Coin(3) => 5000,
-            CardType::Coin(_) => unreachable!(),
-            CardType::Take2 => 500,
-            CardType::NextWave => 50,
-            CardType::Buff(BuffType::Freeze) => 100,
-            CardType::Buff(BuffType::Damage) => 100,
-            CardType::Buff(BuffType::RPM) => 100,
-            CardType::Buff(BuffType::Range) => 100,
-            CardType::Buff(BuffType::Aura) => 300,
-            CardType::DrawPile => 0,
-            CardType::DiscardPile => 0,
+            CardEffect::BuildTower(kind) => {
+                // levels don't have to offer every kind of tower
+                if state.sim.towers.kind(kind).is_none() {
+                    return false;
+                }
+                if !state.sim.map.is_buildable(x, y) || state.sim.towers.has_building(x, y) {
+                    return false;
+                }
+                // enemies have to be able to walk around the new tower in maze mode
+                let enemy_tiles: Vec<(usize, usize)> =
+                    state.sim.enemies.enemies.values().map(|e| e.tile).collect();
+                return state.sim.map.keeps_paths_open(x, y, &enemy_tiles);
+            }
+            CardEffect::SellTower => return state.sim.towers.has_building(x, y),
+            CardEffect::DamageEnemies { range, .. } | CardEffect::DebuffEnemies { range, .. } => {
+                return state
+                    .sim
+                    .enemies
+                    .in_range(GameMap::tile_center(x, y), *range)
+                    .len()
+                    > 0;
+            }
+            CardEffect::Buff(b) => {
+                return state.sim.towers.has_building(x, y)
+                    && state.sim.towers.get_tower(x, y).unwrap().can_have_buff(b);
+            }
+            _ => return true,
         }
     }
 
-    pub fn select(&self, state: &mut PlayingState, slot: usize) {
-        match self {
-            CardType::Empty => {}
-            CardType::Tower => state.gui.set_cursor_card_effect(slot, self),
-            CardType::SellTower => state.gui.set_cursor_card_effect(slot, self),
-            CardType::DamageEnemy => state.gui.set_cursor_card_effect(slot, self),
-            CardType::Shop => state.overlay_state = Some(Box::new(ShopOverlay::new())),
-            CardType::DiscardPile => {
-                state.overlay_state = Some(Box::new(PileOverlay::new(
-                    state.player().deck.discard.clone(),
-                )))
+    // target is the selected tile for cards with Targeting::Tile
+    pub fn apply(&self, state: &mut PlayingState, target: Option<(usize, usize)>) {
+        match (self, target) {
+            (CardEffect::BuildTower(kind), Some((x, y))) => {
+                let stats = state.sim.towers.kind(kind).unwrap();
+                let tower = Tower::new(stats, (x, y), state.sim.me);
+                let tower = state.sim.towers.spawn(tower);
+                state.sim.map.place_tower(x, y);
+                state
+                    .sim
+                    .events
+                    .publish(GameEvent::TowerBuilt { tower, x, y });
+            }
+            (CardEffect::SellTower, Some((x, y))) => {
+                let percent = state.sim.settings.sell_refund;
+                let refund = state.sim.towers.refund_at(x, y, percent).unwrap_or(0);
+                if let Some(tower) = state.sim.towers.remove_tower(x, y) {
+                    state.sim.map.remove_tower(x, y);
+                    state.sim.earn_gold(tower.owner, refund);
+                    state.sim.events.publish(GameEvent::TowerSold {
+                        tower: tower.id,
+                        x,
+                        y,
+                    });
+                }
+            }
+            (CardEffect::DamageEnemies { damage, range }, Some((x, y))) => {
+                for e in state
+                    .sim
+                    .enemies
+                    .in_range(GameMap::tile_center(x, y), *range)
+                {
+                    let position = state.sim.enemies.enemies[&e].position;
+                    let player = state.sim.me;
+                    if state.sim.enemies.damage(e, *damage, player) {
+                        state.sim.events.publish(GameEvent::EnemyKilled {
+                            enemy: e,
+                            by_tower: None,
+                            by_player: player,
+                            position,
+                        });
+                    }
+                }
+            }
+            (CardEffect::DebuffEnemies { debuff, range }, Some((x, y))) => {
+                let mut debuff = debuff.clone();
+                debuff.owner = state.sim.me;
+                let debuffs = [(debuff.kind, debuff)].iter().cloned().collect();
+                for e in state
+                    .sim
+                    .enemies
+                    .in_range(GameMap::tile_center(x, y), *range)
+                {
+                    state.sim.enemies.debuff(e, &debuffs);
+                }
+            }
+            (CardEffect::Buff(b), Some((x, y))) => {
+                state.sim.towers.add_buff_at_pos(x, y, *b);
+                let tower = state.sim.towers.get_tower(x, y).unwrap().id;
+                state.sim.events.publish(GameEvent::TowerBuffed {
+                    tower,
+                    x,
+                    y,
+                    buff: *b,
+                });
+            }
+            (CardEffect::AddGold(gold), _) => state.player_mut().gold += gold,
+            (CardEffect::DrawCards(n), _) => state.sim.draw_cards(*n),
+            (CardEffect::CallNextWave, _) => {
+                let player = state.sim.me;
+                Waves::call_next(&mut state.sim, player);
             }
-            CardType::DrawPile => {
+            (CardEffect::OpenShop, _) => state.overlay_state = Some(Box::new(ShopOverlay::new())),
+            (CardEffect::ShowDrawPile, _) => {
+                let cards = state.player().deck.deck.clone();
                 state.overlay_state =
-                    Some(Box::new(PileOverlay::new(state.player().deck.deck.clone())))
+                    Some(Box::new(PileOverlay::new(cards, &mut state.cosmetic_rng)))
             }
-            CardType::Coin(a) => {
+            (CardEffect::ShowDiscardPile, _) => {
+                let cards = state.player().deck.discard.clone();
+                state.overlay_state =
+                    Some(Box::new(PileOverlay::new(cards, &mut state.cosmetic_rng)))
+            }
+            // tile effects on an instant card have nothing to act on
+            (_, None) => {}
+        }
+    }
+}
+
+impl CardType {
+    pub fn new(name: &str) -> Self {
+        return CardType(name.to_string());
+    }
+
+    pub fn spec<'a>(&self, state: &'a PlayingState) -> &'a CardSpec {
+        return state.sim.cards.get(self);
+    }
+
+    pub fn get_image_id(&self, state: &PlayingState) -> ImgID {
+        return self.spec(state).image;
+    }
+
+    pub fn get_preview_image_id(&self, state: &PlayingState) -> ImgID {
+        return self.get_image_id(state);
+    }
+
+    pub fn get_description(&self, state: &PlayingState) -> String {
+        return self.spec(state).description.clone();
+    }
+
+    // Shown next to the map cursor before the card is activated on x, y
+    pub fn get_preview_info(&self, state: &PlayingState, x: usize, y: usize) -> Option<String> {
+        let spec = self.spec(state);
+        if spec.effects.contains(&CardEffect::SellTower) {
+            let percent = state.sim.settings.sell_refund;
+            let refund = state.sim.towers.refund_at(x, y, percent)?;
+            return Some(format!("Refund: {} gold", refund));
+        }
+        return None;
+    }
+
+    pub fn activation_cost_gold(&self, state: &PlayingState) -> usize {
+        let spec = self.spec(state);
+        let effects: usize = spec.effects.iter().map(|e| e.cost_gold(state)).sum();
+        return spec.gold_cost + effects;
+    }
+
+    pub fn activation_cost_mana(&self, state: &PlayingState) -> usize {
+        let spec = self.spec(state);
+        let effects: usize = spec.effects.iter().map(|e| e.cost_mana(state)).sum();
+        return spec.mana_cost + effects;
+    }
+
+    pub fn aquisition_cost(&self, state: &PlayingState) -> usize {
+        return self.spec(state).aquisition_cost;
+    }
+
+    pub fn select(&self, state: &mut PlayingState, slot: usize) {
+        let spec = self.spec(state).clone();
+        match spec.target {
+            Targeting::Tile => state.gui.set_cursor_card_effect(slot, self),
+            Targeting::Instant => {
                 self.apply_cost(state);
-                state.player_mut().gold += (10 as usize).pow(*a as u32);
-                state.player_mut().deck.card_used(slot);
+                for effect in spec.effects.iter() {
+                    effect.apply(state, None);
+                }
+                state.sim.card_used(slot);
                 let cards = state.player().deck.hand.len();
                 if slot > 0 && slot == cards {
                     state.gui.set_cursor(CursorMode::Actions(slot - 1));
                 }
             }
-            CardType::Take2 => {
-                self.apply_cost(state);
-                state.player_mut().deck.draw(2);
-                state.player_mut().deck.card_used(slot);
-            }
-            CardType::Buff(BuffType::Freeze) => state.gui.set_cursor_card_effect(slot, self),
-            CardType::Buff(BuffType::Damage) => state.gui.set_cursor_card_effect(slot, self),
-            CardType::Buff(BuffType::Range) => state.gui.set_cursor_card_effect(slot, self),
-            CardType::Buff(BuffType::RPM) => state.gui.set_cursor_card_effect(slot, self),
-            CardType::Buff(BuffType::Aura) => state.gui.set_cursor_card_effect(slot, self),
-            CardType::NextWave => {
-                if let WaveStatus::Waiting(_) = state.waves.status {
-                    state.waves.status = WaveStatus::Waiting(0);
-                }
-            }
         }
     }
 
-    pub fn is_selectable(&self, state: &PlayingState, slot: usize) -> bool {
+    pub fn is_selectable(&self, state: &PlayingState, _slot: usize) -> bool {
         if state.player().gold < self.activation_cost_gold(state) {
             return false;
         }
@@ -194,36 +346,14 @@ impl CardType {
     }
 
     pub fn is_applicable(&self, state: &PlayingState, x: usize, y: usize) -> bool {
-        if state.player().gold < self.activation_cost_gold(state) {
+        if !self.is_selectable(state, 0) {
             return false;
         }
-        if (state.player().mana as usize) < self.activation_cost_mana(state) {
+        let spec = self.spec(state);
+        if spec.target != Targeting::Tile {
             return false;
         }
-        match self {
-            CardType::Empty => return false,
-            CardType::Tower => {
-                return state.map.is_buildable(x, y) && !state.towers.has_building(x, y);
-            }
-            CardType::SellTower => return state.towers.has_building(x, y),
-            CardType::DamageEnemy => {
-                return state
-                    .enemies
-                    .in_range(GameMap::tile_center(x, y), 80.0)
-                    .len()
-                    > 0;
-            }
-            CardType::Shop => return false,
-            CardType::Coin(_) => return false,
-            CardType::Take2 => return false,
-            CardType::NextWave => return false,
-            CardType::Buff(b) => {
-                return state.towers.has_building(x, y)
-                    && state.towers.get_tower(x, y).unwrap().can_have_buff(b);
-            }
-            CardType::DrawPile => return false,
-            CardType::DiscardPile => return false,
-        }
+        return spec.effects.iter().all(|e| e.is_applicable(state, x, y));
     }
 
     pub fn apply_cost(&self, state: &mut PlayingState) {
@@ -232,37 +362,17 @@ impl CardType {
     }
 
     pub fn activate(&self, state: &mut PlayingState, x: usize, y: usize) {
+        let gold = self.activation_cost_gold(state);
         self.apply_cost(state);
-        match self {
-            CardType::Empty => {}
-            CardType::Tower => {
-                state.towers.spawn(Tower::new((x, y)));
-                state.gui.set_cursor(CursorMode::Actions(0));
-                let pos = GameMap::tile_center(x, y);
-                state.effects.smoke(pos.x, pos.y)
-            }
-            CardType::SellTower => {
-                state.towers.remove_tower(x, y);
-                state.gui.set_cursor(CursorMode::Actions(0));
-            }
-            CardType::DamageEnemy => {
-                for e in state.enemies.in_range(GameMap::tile_center(x, y), 80.0) {
-                    state.enemies.damage(e, 150);
-                }
-                state.gui.set_cursor(CursorMode::Actions(0));
-            }
-            CardType::Shop => {}
-            CardType::DrawPile => {}
-            CardType::DiscardPile => {}
-            CardType::Coin(_) => {}
-            CardType::Take2 => {}
-            CardType::NextWave => {}
-            CardType::Buff(b) => {
-                state.towers.add_buff_at_pos(x, y, *b);
-                state.gui.set_cursor(CursorMode::Actions(0));
-                let pos = GameMap::tile_center(x, y);
-                state.effects.buff(pos.x, pos.y, b)
+        let spec = self.spec(state).clone();
+        for effect in spec.effects.iter() {
+            effect.apply(state, Some((x, y)));
+        }
+        if spec.effects.iter().any(|e| e.invests_in_tower()) {
+            if let Some(tower) = state.sim.towers.get_tower_mut(x, y) {
+                tower.invested += gold;
             }
         }
+        state.gui.set_cursor(CursorMode::Actions(0));
     }
 }
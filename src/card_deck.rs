@@ -1,7 +1,7 @@
-use crate::buffs::BuffType;
-use crate::card::CardType;
+use crate::card::{CardSpecs, CardType};
+use crate::level::PlayerSettings;
 use rand::seq::SliceRandom;
-use rand::thread_rng;
+use rand::Rng;
 
 pub struct CardDeck {
     pub hand: Vec<CardType>,
@@ -11,28 +11,10 @@ pub struct CardDeck {
 }
 
 impl CardDeck {
-    pub fn all() -> Self {
+    pub fn all(cards: &CardSpecs) -> Self {
         let hand = vec![];
-        let deck = vec![
-            CardType::SellTower,
-            CardType::DamageEnemy,
-            CardType::Coin(1),
-            CardType::Coin(2),
-            CardType::Coin(3),
-            CardType::Take2,
-            CardType::Buff(BuffType::Freeze),
-            CardType::Buff(BuffType::Range),
-            CardType::Buff(BuffType::Damage),
-            CardType::Buff(BuffType::RPM),
-            CardType::Buff(BuffType::Aura),
-        ];
-        let actions = vec![
-            CardType::NextWave,
-            CardType::Tower,
-            CardType::Shop,
-            CardType::DrawPile,
-            CardType::DiscardPile,
-        ];
+        let deck = cards.shop();
+        let actions = CardDeck::named(&["NextWave", "Tower", "Shop", "DrawPile", "DiscardPile"]);
         let discard = vec![];
         Self {
             hand,
@@ -42,34 +24,27 @@ impl CardDeck {
         }
     }
 
-    pub fn new() -> Self {
-        let hand = vec![CardType::Tower, CardType::Coin(1)];
-        let deck = vec![];
-        let discard = vec![];
-        let actions = vec![
-            CardType::NextWave,
-            CardType::Shop,
-            CardType::DrawPile,
-            CardType::DiscardPile,
-        ];
-        Self {
-            hand,
-            deck,
-            actions,
-            discard,
-        }
+    pub fn new(settings: &PlayerSettings) -> Self {
+        return Self {
+            hand: settings.hand.clone(),
+            deck: settings.deck.clone(),
+            actions: settings.actions.clone(),
+            discard: vec![],
+        };
+    }
+
+    fn named(names: &[&str]) -> Vec<CardType> {
+        return names.iter().map(|n| CardType::new(n)).collect();
     }
 
     pub fn discard_all(&mut self) {
-        self.discard
-            .extend(self.hand.drain(..).filter(|c| c != &CardType::Empty));
+        self.discard.extend(self.hand.drain(..));
     }
 
-    pub fn card_used(&mut self, slot: usize) {
+    pub fn card_used<R: Rng>(&mut self, slot: usize, rng: &mut R) {
         if slot < self.hand.len() {
-            assert!(self.hand[slot] != CardType::Empty);
-            self.discard.push(self.hand[slot]);
-            if let Some(card) = self.draw_one() {
+            self.discard.push(self.hand[slot].clone());
+            if let Some(card) = self.draw_one(rng) {
                 self.hand[slot] = card;
             } else {
                 self.hand.remove(slot);
@@ -77,8 +52,8 @@ impl CardDeck {
         }
     }
 
-    pub fn shuffle(&mut self) {
-        self.deck.as_mut_slice().shuffle(&mut thread_rng());
+    pub fn shuffle<R: Rng>(&mut self, rng: &mut R) {
+        self.deck.as_mut_slice().shuffle(rng);
     }
 
     pub fn get_selected_card(&self, slot: usize) -> Option<&CardType> {
@@ -88,18 +63,18 @@ impl CardDeck {
         return self.actions.get(slot - self.hand.len());
     }
 
-    pub fn draw(&mut self, n: usize) {
+    pub fn draw<R: Rng>(&mut self, n: usize, rng: &mut R) {
         for _ in 0..n {
-            if let Some(card) = self.draw_one() {
+            if let Some(card) = self.draw_one(rng) {
                 self.hand.push(card);
             }
         }
     }
 
-    pub fn draw_one(&mut self) -> Option<CardType> {
+    pub fn draw_one<R: Rng>(&mut self, rng: &mut R) -> Option<CardType> {
         if self.deck.is_empty() {
             self.deck.append(&mut self.discard);
-            self.shuffle()
+            self.shuffle(rng)
         }
         return self.deck.pop();
     }
@@ -0,0 +1,113 @@
+use crate::card::CardType;
+use crate::game_events::GameEvent;
+use crate::gui::CursorMode;
+use crate::playing_state::PlayingState;
+use crate::shop_overlay::ShopOverlay;
+use crate::wave::Waves;
+
+// Everything a player can do to change the game. Keyboard input, replays and
+// any other source of input are turned into commands and run through
+// PlayingState::execute, so they all get the same validation.
+#[derive(Eq, PartialEq, Hash, Clone, Debug, Serialize, Deserialize)]
+pub enum Command {
+    PlayCard { slot: usize },
+    ActivateAt { x: usize, y: usize },
+    BuyCard(CardType),
+    // switches the tower at x, y to its next targeting mode
+    CycleTargeting { x: usize, y: usize },
+    // upgrades the tower at x, y along the given branch of its upgrade tree
+    UpgradeTower { x: usize, y: usize, branch: usize },
+    CallNextWave,
+    Cancel,
+}
+
+impl Command {
+    pub fn is_valid(&self, state: &PlayingState) -> bool {
+        match self {
+            Command::PlayCard { slot } => {
+                if let Some(card) = state.player().deck.get_selected_card(*slot) {
+                    return card.is_selectable(state, *slot);
+                }
+                return false;
+            }
+            Command::ActivateAt { x, y } => {
+                if !state.sim.map.valid_tile_pos(*x as isize, *y as isize) {
+                    return false;
+                }
+                if let CursorMode::Map { card, .. } = state.gui.cursor() {
+                    return card.is_applicable(state, *x, *y);
+                }
+                return false;
+            }
+            Command::BuyCard(card) => {
+                return ShopOverlay::get_available_cards(state).contains(card)
+                    && state.player().gold >= card.aquisition_cost(state);
+            }
+            Command::CycleTargeting { x, y } => {
+                if let Some(tower) = state.sim.towers.get_tower(*x, *y) {
+                    return tower.owner == state.sim.me;
+                }
+                return false;
+            }
+            Command::UpgradeTower { x, y, branch } => {
+                let owned = match state.sim.towers.get_tower(*x, *y) {
+                    Some(tower) => tower.owner == state.sim.me,
+                    None => false,
+                };
+                if let Some(upgrade) = state.sim.towers.upgrade_at(*x, *y, *branch) {
+                    return owned
+                        && state.player().gold >= upgrade.price
+                        && state.player().mana >= upgrade.mana_cost as f32;
+                }
+                return false;
+            }
+            Command::CallNextWave => return Waves::can_call_next(&state.sim),
+            Command::Cancel => return true,
+        }
+    }
+
+    pub fn apply(&self, state: &mut PlayingState) {
+        match self {
+            Command::PlayCard { slot } => {
+                if let Some(card) = state.player().deck.get_selected_card(*slot) {
+                    card.clone().select(state, *slot);
+                }
+            }
+            Command::ActivateAt { x, y } => {
+                if let CursorMode::Map { card, slot, .. } = state.gui.cursor() {
+                    card.activate(state, *x, *y);
+                    state.sim.card_used(slot);
+                }
+            }
+            Command::BuyCard(card) => {
+                state.player_mut().gold -= card.aquisition_cost(state);
+                state.player_mut().deck.buy_card(card.clone());
+            }
+            Command::CycleTargeting { x, y } => {
+                if let Some(tower) = state.sim.towers.get_tower_mut(*x, *y) {
+                    tower.targeting = tower.targeting.next();
+                }
+            }
+            Command::UpgradeTower { x, y, branch } => {
+                let upgrade = state.sim.towers.upgrade_at(*x, *y, *branch).unwrap();
+                let (price, mana_cost) = (upgrade.price, upgrade.mana_cost);
+                state.player_mut().gold -= price;
+                state.player_mut().mana -= mana_cost as f32;
+                let tower = state.sim.towers.get_tower_mut(*x, *y).unwrap();
+                tower.upgrade(*branch);
+                tower.invested += price;
+                let tower = tower.id;
+                state.sim.events.publish(GameEvent::TowerUpgraded {
+                    tower,
+                    x: *x,
+                    y: *y,
+                });
+            }
+            Command::CallNextWave => {
+                let player = state.sim.me;
+                Waves::call_next(&mut state.sim, player);
+            }
+            Command::Cancel => state.gui.set_cursor(CursorMode::Actions(0)),
+        }
+    }
+}
@@ -1,19 +1,173 @@
 use crate::buffs::Buff;
 use crate::buffs::BuffType;
+use crate::utils::load_specs;
+use std::collections::HashMap;
 
-#[derive(Debug, Deserialize, Clone)]
+#[derive(Eq, PartialEq, Hash, Copy, Clone, Debug, Serialize, Deserialize)]
+pub enum DebuffType {
+    // walks effectiveness percent slower
+    Slow,
+    // takes effectiveness damage right after being hit and then every POISON_INTERVAL ticks,
+    // armor, shields and abilities reacting to hits don't apply
+    Poison,
+    // doesn't move at all
+    Stun,
+    // armor is reduced by effectiveness
+    ArmorShred,
+    // takes effectiveness percent more damage
+    Vulnerability,
+}
+
+pub const POISON_INTERVAL: usize = 30;
+
+// What happens when an enemy gets a debuff of a kind it already has
+#[derive(PartialEq, Copy, Clone, Debug, Deserialize)]
+pub enum Stacking {
+    // only the most effective one is kept, equal ones refresh the duration
+    Strongest,
+    // the new one always replaces the old one
+    Refresh,
+    // all of them add up, the ones closest to running out are dropped above the limit
+    Stack(usize),
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct DebuffStats {
+    pub kind: DebuffType,
+    pub stacking: Stacking,
+}
+
+pub struct DebuffSpecs {
+    pub specs: HashMap<DebuffType, DebuffStats>,
+}
+
+impl DebuffSpecs {
+    pub fn new() -> Self {
+        let mut specs = HashMap::new();
+        for spec in load_specs::<DebuffStats>("debuffs") {
+            specs.insert(spec.kind, spec);
+        }
+        return Self { specs };
+    }
+
+    pub fn stacking(&self, kind: DebuffType) -> Stacking {
+        return match self.specs.get(&kind) {
+            Some(spec) => spec.stacking,
+            None => Stacking::Strongest,
+        };
+    }
+}
+
+#[derive(PartialEq, Debug, Serialize, Deserialize, Clone)]
 pub struct Debuff {
-    pub kind: BuffType,
-    pub cooldown: usize,
+    pub kind: DebuffType,
+    // ticks left
+    pub duration: usize,
     pub effectiveness: usize,
+    // the player that gets the bounty if poison kills the enemy
+    #[serde(default)]
+    pub owner: usize,
+    // ticks since it was applied
+    #[serde(default)]
+    pub elapsed: usize,
 }
 
 impl Debuff {
-    pub fn new(buff: Buff) -> Self {
-        return Self {
-            kind: buff.stats.kind,
+    // Tower buffs that affect the enemies hit by the tower's projectiles
+    pub fn from_buff(buff: &Buff) -> Option<Self> {
+        let kind = match buff.stats.kind {
+            BuffType::Freeze => DebuffType::Slow,
+            _ => return None,
+        };
+        return Some(Self {
+            kind,
             effectiveness: buff.effectiveness(),
-            cooldown: buff.effectiveness(),
+            duration: buff.cooldown(),
+            owner: 0,
+            elapsed: 0,
+        });
+    }
+
+    // Adds the debuff to the ones of the same kind an enemy already has
+    pub fn stack(self, debuffs: &mut Vec<Debuff>, stacking: Stacking) {
+        match stacking {
+            Stacking::Strongest => {
+                if let Some(old) = debuffs.first() {
+                    if old.effectiveness > self.effectiveness
+                        || (old.effectiveness == self.effectiveness && old.duration > self.duration)
+                    {
+                        return;
+                    }
+                }
+                debuffs.clear();
+                debuffs.push(self);
+            }
+            Stacking::Refresh => {
+                debuffs.clear();
+                debuffs.push(self);
+            }
+            Stacking::Stack(max) => {
+                debuffs.push(self);
+                debuffs.sort_by_key(|d| std::cmp::Reverse(d.duration));
+                debuffs.truncate(max);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn debuff(effectiveness: usize, duration: usize) -> Debuff {
+        return Debuff {
+            kind: DebuffType::Slow,
+            duration,
+            effectiveness,
+            owner: 0,
+            elapsed: 0,
         };
     }
+
+    fn stack(debuffs: &[Debuff], stacking: Stacking) -> Vec<(usize, usize)> {
+        let mut stacked = vec![];
+        for d in debuffs.iter() {
+            d.clone().stack(&mut stacked, stacking);
+        }
+        return stacked
+            .iter()
+            .map(|d| (d.effectiveness, d.duration))
+            .collect();
+    }
+
+    #[test]
+    fn strongest_keeps_the_most_effective() {
+        let debuffs = [debuff(20, 60), debuff(10, 600), debuff(30, 30)];
+        assert_eq!(stack(&debuffs, Stacking::Strongest), vec![(30, 30)]);
+        // equal ones refresh the duration, but never shorten it
+        let debuffs = [debuff(20, 60), debuff(20, 90), debuff(20, 30)];
+        assert_eq!(stack(&debuffs, Stacking::Strongest), vec![(20, 90)]);
+    }
+
+    #[test]
+    fn refresh_keeps_the_newest() {
+        let debuffs = [debuff(30, 60), debuff(10, 20)];
+        assert_eq!(stack(&debuffs, Stacking::Refresh), vec![(10, 20)]);
+    }
+
+    #[test]
+    fn stack_drops_the_ones_running_out_first() {
+        let debuffs = [debuff(1, 60), debuff(2, 30), debuff(3, 90), debuff(4, 45)];
+        assert_eq!(
+            stack(&debuffs, Stacking::Stack(3)),
+            vec![(3, 90), (1, 60), (4, 45)]
+        );
+    }
+
+    #[test]
+    fn the_specs_know_every_kind() {
+        let specs = DebuffSpecs::new();
+        assert_eq!(specs.stacking(DebuffType::Poison), Stacking::Stack(5));
+        assert_eq!(specs.stacking(DebuffType::Stun), Stacking::Refresh);
+    }
 }
@@ -1,6 +1,8 @@
 use crate::algebra::{Point, Vector};
 use crate::assets::{Data, ImgID};
 use crate::buffs::BuffType;
+use crate::game_events::GameEvent;
+use crate::map::GameMap;
 use crate::playing_state::PlayingState;
 use ggez::graphics;
 use ggez::{Context, GameResult};
@@ -82,6 +84,25 @@ impl Effects {
         return Ok(());
     }
 
+    pub fn on_event(&mut self, event: &GameEvent) {
+        match event {
+            GameEvent::EnemySpawned { position, .. } => self.fire(position.x, position.y),
+            GameEvent::EnemyHit { position, .. } => self.smoke(position.x, position.y),
+            GameEvent::TowerBuilt { x, y, .. } | GameEvent::TowerUpgraded { x, y, .. } => {
+                let pos = GameMap::tile_center(*x, *y);
+                self.smoke(pos.x, pos.y);
+            }
+            GameEvent::TowerBuffed { x, y, buff, .. } => {
+                let pos = GameMap::tile_center(*x, *y);
+                self.buff(pos.x, pos.y, buff);
+            }
+            GameEvent::BeamFired { from, to, img } => self
+                .effects
+                .push(Box::new(BeamEffect::new(*from, *to, *img))),
+            _ => {}
+        }
+    }
+
     pub fn smoke(&mut self, x: f32, y: f32) {
         self.effects
             .push(Box::new(SmokeEffect::new(x, y, 4.0, ImgID::Smoke)));
@@ -149,3 +170,42 @@ impl SmokeEffect {
         return p;
     }
 }
+
+// A line of the projectile's sprite from the tower to the enemy that fades out
+struct BeamEffect {
+    particles: Vec<ParticleData>,
+}
+
+impl Effect for BeamEffect {
+    fn get_particles(&self) -> &Vec<ParticleData> {
+        return &self.particles;
+    }
+
+    fn tick(&mut self) {
+        for p in self.particles.iter_mut() {
+            p.alpha *= 0.7;
+            p.ttl -= 1.0;
+        }
+        self.particles.retain(|e| e.ttl > 0.0);
+    }
+}
+
+impl BeamEffect {
+    pub fn new(from: Point, to: Point, img: ImgID) -> Self {
+        let dir = to - from;
+        let rotation = dir.y.atan2(dir.x);
+        // one sprite every 12 pixels, the arrow sized sprites are 16 pixels long when drawn
+        let steps = (dir.norm() / 12.0).ceil().max(1.0) as usize;
+        let particles = (0..=steps)
+            .map(|i| {
+                let pos = from + dir * (i as f32 / steps as f32);
+                let mut p = ParticleData::new(img, pos.x, pos.y);
+                p.size = 4.0;
+                p.rotation = rotation;
+                p.ttl = 10.0;
+                return p;
+            })
+            .collect::<Vec<_>>();
+        return Self { particles };
+    }
+}
@@ -9,21 +9,23 @@ use ggez::{Context, GameResult};
 
 pub struct EndState {
     victory: bool,
+    summary: String,
     data: Option<Data>,
 }
 
 impl EndState {
-    fn new(victory: bool) -> Self {
+    fn new(victory: bool, summary: String) -> Self {
         return Self {
             victory,
+            summary,
             data: None,
         };
     }
-    pub fn failed() -> Self {
-        return EndState::new(false);
+    pub fn failed(summary: String) -> Self {
+        return EndState::new(false, summary);
     }
-    pub fn win() -> Self {
-        return EndState::new(true);
+    pub fn win(summary: String) -> Self {
+        return EndState::new(true, summary);
     }
 }
 
@@ -55,6 +57,15 @@ impl event_handler::GameState for EndState {
                 .scale(Vector::new(0.3, 0.3))
                 .color(color),
         )?;
+        let summary = utils::text(self.data.as_ref().unwrap(), &self.summary);
+        graphics::draw(
+            ctx,
+            &summary,
+            graphics::DrawParam::default()
+                .dest(Point::new(300.0, 160.0))
+                .offset(Point::new(0.0, 0.0))
+                .scale(Vector::new(0.2, 0.2)),
+        )?;
         graphics::present(ctx)?;
         Ok(())
     }
@@ -67,7 +78,7 @@ impl event_handler::GameState for EndState {
         _repeat: bool,
     ) -> StateTransition {
         match keycode {
-            KeyCode::Space => return StateTransition::Next(Box::new(MenuState::new())),
+            KeyCode::Space => return StateTransition::Next(Box::new(MenuState::new(None, None))),
             _ => {}
         }
         return StateTransition::Stay;
@@ -1,49 +1,103 @@
 use crate::algebra::{Point, Vector};
 use crate::assets::Data;
-use crate::buffs::BuffType;
-use crate::debuffs::Debuff;
-use crate::enemy::Enemy;
+use crate::debuffs::{Debuff, DebuffSpecs, DebuffType, POISON_INTERVAL};
+use crate::enemy::{Ability, Enemy, EnemyArchetype};
+use crate::game_events::GameEvent;
+use crate::map::GameMap;
 use crate::playing_state::PlayingState;
-use crate::utils::distance;
+use crate::simulation::Simulation;
+use crate::tower::TargetingMode;
+use crate::utils::{distance, invalid_spec, lerp, load_specs};
 use ggez::graphics;
 use ggez::{Context, GameResult};
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
+use std::rc::Rc;
 
 pub struct Enemies {
-    pub enemies: HashMap<usize, Enemy>,
-    id: usize,
+    pub archetypes: HashMap<String, Rc<EnemyArchetype>>,
+    pub debuff_specs: DebuffSpecs,
+    pub enemies: BTreeMap<usize, Enemy>,
+    pub id: usize,
 }
 
 impl Enemies {
     pub fn new() -> Self {
         let id = 0;
-        let enemies = HashMap::new();
-        return Self { enemies, id };
+        let enemies = BTreeMap::new();
+        let mut archetypes = HashMap::new();
+        for archetype in load_specs::<EnemyArchetype>("enemies") {
+            archetypes.insert(archetype.name.clone(), Rc::new(archetype));
+        }
+        for archetype in archetypes.values() {
+            if archetype.health == 0 || archetype.speed <= 0.0 {
+                invalid_spec(
+                    "resources/rons/enemies.ron",
+                    &format!("{} needs health and speed", archetype.name),
+                );
+            }
+            for ability in archetype.abilities.iter() {
+                if let Ability::Split { into, .. } = ability {
+                    if !archetypes.contains_key(into) {
+                        invalid_spec(
+                            "resources/rons/enemies.ron",
+                            &format!("{} splits into unknown enemy {}", archetype.name, into),
+                        );
+                    }
+                }
+            }
+        }
+        return Self {
+            archetypes,
+            debuff_specs: DebuffSpecs::new(),
+            enemies,
+            id,
+        };
+    }
+
+    pub fn archetype(&self, name: &str) -> Rc<EnemyArchetype> {
+        return self
+            .archetypes
+            .get(name)
+            .expect(&format!("Unknown enemy archetype {}", name))
+            .clone();
+    }
+
+    // The archetype with its health and speed replaced, used for scaled up waves
+    pub fn archetype_with(&self, name: &str, health: usize, speed: f32) -> Rc<EnemyArchetype> {
+        let archetype = self.archetype(name);
+        if archetype.health == health && archetype.speed == speed {
+            return archetype;
+        }
+        let mut scaled = (*archetype).clone();
+        scaled.health = health;
+        scaled.speed = speed;
+        return Rc::new(scaled);
     }
 
-    pub fn spawn(&mut self, enemy: Enemy) {
-        self.enemies.insert(self.id, enemy);
+    pub fn spawn(&mut self, enemy: Enemy) -> usize {
+        let id = self.id;
+        self.enemies.insert(id, enemy);
         self.id += 1;
+        return id;
     }
 
     pub fn draw(state: &PlayingState, data: &Data, ctx: &mut Context) -> GameResult<()> {
-        for e in state.enemies.enemies.values() {
-            let mut color = e.color;
-            match e.debuffs.get(&BuffType::Freeze) {
-                Some(debuffs) => {
-                    if debuffs.len() > 0 {
-                        color = (0.5, 5.0, 1.0);
-                    }
-                }
-                None => (),
+        for e in state.sim.enemies.enemies.values() {
+            let mut color = e.stats.color;
+            if e.is_debuffed(DebuffType::Slow) {
+                color = (0.5, 5.0, 1.0);
             }
             graphics::draw(
                 ctx,
-                data.get_i(&e.disp),
+                data.get_i(&e.stats.img),
                 graphics::DrawParam::default()
-                    .dest(state.gui.cam().world_pos(e.position))
+                    .dest(state.gui.cam().world_pos(lerp(
+                        e.last_position,
+                        e.position,
+                        state.interpolation,
+                    )))
                     .offset(Point::new(0.5, 0.5))
-                    .scale(Vector::new(4.0 * e.size, 4.0 * e.size))
+                    .scale(Vector::new(4.0 * e.stats.size, 4.0 * e.stats.size))
                     .color(graphics::Color::new(color.0, color.1, color.2, 1.0)),
             )?;
         }
@@ -58,48 +112,348 @@ impl Enemies {
             .collect()
     }
 
-    pub fn weakest_enemy_in_range(&self, range: f32, pos: Point) -> Option<usize> {
-        self.in_range(pos, range)
-            .iter()
-            .min_by_key(|id| self.enemies.get(id).unwrap().health)
-            .cloned()
-    }
-
-    pub fn tick(state: &mut PlayingState) {
-        for e in state.enemies.enemies.values_mut() {
-            e.tick(&state.map)
-        }
-        state.enemies.enemies.retain(|_id, e| e.health > 0);
-        state.player_mut().hp = state.player_mut().hp.saturating_sub(
-            state
-                .enemies
-                .enemies
-                .iter()
-                .filter(|(_id, e)| e.reached_goal)
-                .count(),
-        );
-        state
+    // The enemies in range, the best target for the mode first
+    pub fn targets_in_range(
+        &self,
+        range: f32,
+        pos: Point,
+        mode: TargetingMode,
+        map: &GameMap,
+    ) -> Vec<usize> {
+        // enemies with a smaller key are better targets
+        let key = |id: &usize| -> (f32, f32) {
+            let e = &self.enemies[id];
+            // distance to the goal, the flow field only knows it per tile
+            let to_goal = (
+                map.flow_field.distance(e.tile.0, e.tile.1) as f32,
+                distance(&e.position, &e.next_walk_target),
+            );
+            return match mode {
+                TargetingMode::First => to_goal,
+                TargetingMode::Last => (-to_goal.0, -to_goal.1),
+                TargetingMode::Strongest => (-(e.health as f32), 0.0),
+                TargetingMode::Weakest => (e.health as f32, 0.0),
+                TargetingMode::Closest => (distance(&pos, &e.position), 0.0),
+                TargetingMode::Fastest => (-e.get_walk_speed(), 0.0),
+            };
+        };
+        let mut targets = self.in_range(pos, range);
+        targets.sort_by(|a, b| key(a).partial_cmp(&key(b)).unwrap());
+        return targets;
+    }
+
+    pub fn tick(sim: &mut Simulation) {
+        // before the debuffs count down, so poison applied last tick hits right away
+        Enemies::poison(sim);
+        for e in sim.enemies.enemies.values_mut() {
+            e.tick(&sim.map)
+        }
+        Enemies::heal_allies(sim);
+        Enemies::split_dead(sim);
+        Enemies::pay_bounties(sim);
+        sim.enemies.enemies.retain(|_id, e| e.health > 0);
+        let leaked = sim
             .enemies
             .enemies
-            .retain(|_id, e| e.reached_goal == false);
+            .iter()
+            .filter(|(_id, e)| e.reached_goal)
+            .map(|(id, _e)| *id)
+            .collect::<Vec<_>>();
+        sim.player_mut().hp = sim.player_mut().hp.saturating_sub(leaked.len());
+        for enemy in leaked {
+            sim.events.publish(GameEvent::EnemyLeaked { enemy });
+        }
+        sim.enemies.enemies.retain(|_id, e| e.reached_goal == false);
     }
 
-    pub fn debuff(&mut self, id: usize, debuffs: &HashMap<BuffType, Debuff>) {
+    fn heal_allies(sim: &mut Simulation) {
+        let mut heals = vec![];
+        for (id, e) in sim.enemies.enemies.iter() {
+            for ability in e.stats.abilities.iter() {
+                if let Ability::HealAllies {
+                    amount,
+                    range,
+                    interval,
+                } = ability
+                {
+                    if e.health > 0 && e.age % interval.max(&1) == 0 {
+                        for other in sim.enemies.in_range(e.position, *range) {
+                            if other != *id {
+                                heals.push((other, *amount));
+                            }
+                        }
+                    }
+                }
+            }
+        }
+        for (id, amount) in heals {
+            sim.enemies.enemies.get_mut(&id).unwrap().heal(amount);
+        }
+    }
+
+    fn split_dead(sim: &mut Simulation) {
+        let mut children = vec![];
+        for e in sim.enemies.enemies.values() {
+            if e.health > 0 {
+                continue;
+            }
+            for ability in e.stats.abilities.iter() {
+                if let Ability::Split { into, count } = ability {
+                    // children of scaled up enemies are scaled up the same way
+                    let base = sim.enemies.archetype(&e.stats.name);
+                    let health = e.stats.health as f32 / base.health as f32;
+                    let speed = e.stats.speed / base.speed;
+                    for _ in 0..*count {
+                        children.push((e.position, e.tile, into.clone(), health, speed));
+                    }
+                }
+            }
+        }
+        for (position, tile, name, health, speed) in children {
+            let base = sim.enemies.archetype(&name);
+            let archetype = sim.enemies.archetype_with(
+                &name,
+                (base.health as f32 * health) as usize,
+                base.speed * speed,
+            );
+            let child = Enemy::at(position, tile, archetype);
+            let enemy = sim.enemies.spawn(child);
+            sim.events
+                .publish(GameEvent::EnemySpawned { enemy, position });
+        }
+    }
+
+    fn pay_bounties(sim: &mut Simulation) {
+        let mut bounties = vec![];
+        for e in sim.enemies.enemies.values() {
+            if let (0, Some(player)) = (e.health, e.killed_by) {
+                let bounty = sim.settings.bounties.get(&e.stats.name).unwrap_or(&0);
+                bounties.push((player, *bounty));
+            }
+        }
+        for (player, bounty) in bounties {
+            sim.earn_gold(player, bounty);
+        }
+    }
+
+    pub fn debuff(&mut self, id: usize, debuffs: &HashMap<DebuffType, Debuff>) {
         if let Some(e) = self.enemies.get_mut(&id) {
-            for (bt, d) in debuffs.iter() {
-                let debuff_vec = e.debuffs.entry(*bt).or_insert(Vec::new());
-                debuff_vec.push(d.clone());
+            for (kind, d) in debuffs.iter() {
+                let stacking = self.debuff_specs.stacking(*kind);
+                let debuff_vec = e.debuffs.entry(*kind).or_insert(Vec::new());
+                d.clone().stack(debuff_vec, stacking);
+            }
+        }
+    }
+
+    fn poison(sim: &mut Simulation) {
+        let mut poisoned = vec![];
+        for (id, e) in sim.enemies.enemies.iter() {
+            if e.health == 0 {
+                continue;
+            }
+            if let Some(debuffs) = e.debuffs.get(&DebuffType::Poison) {
+                // every poison ticks on its own schedule, starting when it was applied
+                for d in debuffs.iter().filter(|d| d.elapsed % POISON_INTERVAL == 0) {
+                    poisoned.push((*id, d.effectiveness, d.owner));
+                }
             }
         }
+        for (id, damage, player) in poisoned {
+            let position = sim.enemies.enemies[&id].position;
+            if sim.enemies.poison_damage(id, damage, player) {
+                sim.events.publish(GameEvent::EnemyKilled {
+                    enemy: id,
+                    by_tower: None,
+                    by_player: player,
+                    position,
+                });
+            }
+        }
+    }
+
+    // Returns true if this damage killed the enemy
+    pub fn damage(&mut self, id: usize, damage: usize, player: usize) -> bool {
+        return self.hurt(id, player, |e| e.take_damage(damage));
+    }
+
+    // Poison eats through armor and shields and doesn't set off abilities that react to hits
+    fn poison_damage(&mut self, id: usize, damage: usize, player: usize) -> bool {
+        return self.hurt(id, player, |e| e.lose_health(damage));
     }
 
-    pub fn damage(&mut self, id: usize, damage: usize) {
+    fn hurt<F: FnOnce(&mut Enemy) -> usize>(&mut self, id: usize, player: usize, f: F) -> bool {
         if let Some(e) = self.enemies.get_mut(&id) {
-            e.health = e.health.saturating_sub(damage);
+            let alive = e.health > 0;
+            f(e);
+            if alive && e.health == 0 {
+                e.killed_by = Some(player);
+                return true;
+            }
         }
+        return false;
     }
 
     pub fn any_alive(&self) -> bool {
         self.enemies.iter().filter(|(_id, e)| e.health > 0).count() > 0
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn spawn(sim: &mut Simulation, name: &str) -> usize {
+        let archetype = sim.enemies.archetype(name);
+        let enemy = Enemy::new(0, &sim.map, archetype);
+        return sim.enemies.spawn(enemy);
+    }
+
+    #[test]
+    fn killers_get_the_bounty() {
+        let mut sim = Simulation::new("tutorial", false, 0);
+        let me = sim.me;
+        sim.settings.bounties.clear();
+        sim.settings.bounties.insert("Zombie".to_string(), 7);
+        let gold = sim.player().gold;
+        let killed = spawn(&mut sim, "Zombie");
+        let leaked = spawn(&mut sim, "Zombie");
+        let brute = spawn(&mut sim, "Brute");
+        assert!(sim.enemies.damage(killed, 100, me));
+        sim.enemies.enemies.get_mut(&leaked).unwrap().reached_goal = true;
+        sim.enemies.damage(brute, 1000, me);
+        Enemies::tick(&mut sim);
+        // leaking pays nothing and the Brute has no bounty
+        assert_eq!(sim.player().gold, gold + 7);
+        assert!(sim.enemies.enemies.is_empty());
+        // and the dead only pay once
+        Enemies::tick(&mut sim);
+        assert_eq!(sim.player().gold, gold + 7);
+    }
+
+    fn poison(damage: usize, duration: usize, owner: usize) -> HashMap<DebuffType, Debuff> {
+        let mut debuffs = HashMap::new();
+        let poison = Debuff {
+            kind: DebuffType::Poison,
+            duration,
+            effectiveness: damage,
+            owner,
+            elapsed: 0,
+        };
+        debuffs.insert(DebuffType::Poison, poison);
+        return debuffs;
+    }
+
+    #[test]
+    fn poison_ticks_right_away_and_then_every_interval() {
+        let mut sim = Simulation::new("tutorial", false, 0);
+        // Knights have armor and shields, poison ignores both
+        let knight = spawn(&mut sim, "Knight");
+        let health = sim.enemies.enemies[&knight].health;
+        sim.enemies
+            .debuff(knight, &poison(3, 2 * POISON_INTERVAL, 0));
+        Enemies::tick(&mut sim);
+        assert_eq!(sim.enemies.enemies[&knight].health, health - 3);
+        assert_eq!(sim.enemies.enemies[&knight].shield, 50);
+        for _ in 0..POISON_INTERVAL {
+            Enemies::tick(&mut sim);
+        }
+        assert_eq!(sim.enemies.enemies[&knight].health, health - 6);
+        // it ran out before the third tick
+        for _ in 0..2 * POISON_INTERVAL {
+            Enemies::tick(&mut sim);
+        }
+        assert_eq!(sim.enemies.enemies[&knight].health, health - 6);
+        assert!(!sim.enemies.enemies[&knight].is_debuffed(DebuffType::Poison));
+    }
+
+    #[test]
+    fn stacked_poisons_tick_on_their_own() {
+        let mut sim = Simulation::new("tutorial", false, 0);
+        let brute = spawn(&mut sim, "Brute");
+        sim.enemies.debuff(brute, &poison(2, 600, 0));
+        Enemies::tick(&mut sim);
+        sim.enemies.debuff(brute, &poison(5, 600, 0));
+        Enemies::tick(&mut sim);
+        assert_eq!(sim.enemies.enemies[&brute].health, 93);
+        for _ in 0..POISON_INTERVAL - 1 {
+            Enemies::tick(&mut sim);
+        }
+        // the first one is due, the second one a tick later
+        assert_eq!(sim.enemies.enemies[&brute].health, 91);
+        Enemies::tick(&mut sim);
+        assert_eq!(sim.enemies.enemies[&brute].health, 86);
+    }
+
+    #[test]
+    fn poison_kills_pay_its_owner() {
+        let mut sim = Simulation::new("tutorial", false, 0);
+        let me = sim.me;
+        sim.settings.bounties.insert("Zombie".to_string(), 7);
+        let gold = sim.player().gold;
+        let zombie = spawn(&mut sim, "Zombie");
+        sim.enemies.debuff(zombie, &poison(100, 60, me));
+        Enemies::tick(&mut sim);
+        assert!(sim.enemies.enemies.is_empty());
+        assert_eq!(sim.player().gold, gold + 7);
+    }
+
+    #[test]
+    fn dead_enemies_split_where_they_died() {
+        let mut sim = Simulation::new("tutorial", false, 0);
+        let behemoth = spawn(&mut sim, "Behemoth");
+        for _ in 0..10 {
+            Enemies::tick(&mut sim);
+        }
+        let position = sim.enemies.enemies[&behemoth].position;
+        assert!(sim.enemies.damage(behemoth, 10_000, 0));
+        Enemies::tick(&mut sim);
+        assert!(!sim.enemies.enemies.contains_key(&behemoth));
+        let children = sim.enemies.enemies.values().collect::<Vec<_>>();
+        assert_eq!(children.len(), 3);
+        for child in children {
+            assert_eq!(child.stats.name, "Runner");
+            assert_eq!(child.health, child.stats.health);
+            // the parent still took its last step
+            assert!(distance(&child.position, &position) <= 1.0);
+        }
+    }
+
+    #[test]
+    fn split_children_are_scaled_like_their_parent() {
+        let mut sim = Simulation::new("tutorial", false, 0);
+        let base = sim.enemies.archetype("Behemoth");
+        let archetype = sim
+            .enemies
+            .archetype_with("Behemoth", base.health * 2, base.speed);
+        let behemoth = sim.enemies.spawn(Enemy::new(0, &sim.map, archetype));
+        sim.enemies.damage(behemoth, 10_000, 0);
+        Enemies::tick(&mut sim);
+        let runner = sim.enemies.archetype("Runner");
+        for child in sim.enemies.enemies.values() {
+            assert_eq!(child.health, runner.health * 2);
+            assert_eq!(child.stats.speed, runner.speed);
+        }
+    }
+
+    #[test]
+    fn healers_heal_others_in_range() {
+        let mut sim = Simulation::new("tutorial", false, 0);
+        let shaman = spawn(&mut sim, "Shaman");
+        let zombie = spawn(&mut sim, "Zombie");
+        sim.enemies.damage(shaman, 50, 0);
+        sim.enemies.damage(zombie, 8, 0);
+        // the Shaman heals 5 every 60 ticks
+        for _ in 0..59 {
+            Enemies::tick(&mut sim);
+        }
+        assert_eq!(sim.enemies.enemies[&zombie].health, 2);
+        Enemies::tick(&mut sim);
+        assert_eq!(sim.enemies.enemies[&zombie].health, 7);
+        assert_eq!(sim.enemies.enemies[&shaman].health, 70);
+        for _ in 0..60 {
+            Enemies::tick(&mut sim);
+        }
+        assert_eq!(sim.enemies.enemies[&zombie].health, 10);
+    }
+}
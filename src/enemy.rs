@@ -1,75 +1,329 @@
 use crate::algebra::Point;
 use crate::assets::ImgID;
-use crate::buffs::BuffType;
-use crate::debuffs::Debuff;
+use crate::debuffs::{Debuff, DebuffType};
 use crate::map::GameMap;
 use crate::utils::move_to;
-use crate::wave::WaveSpec;
 use std::collections::HashMap;
+use std::rc::Rc;
+
+#[derive(PartialEq, Clone, Debug, Deserialize)]
+pub enum Ability {
+    // every hit does this much less damage, but always at least 1
+    Armor(usize),
+    // heals amount every interval ticks
+    Regeneration {
+        amount: usize,
+        interval: usize,
+    },
+    // absorbs this much damage before the enemy loses health
+    Shield(usize),
+    // spawns count enemies of the named archetype where it died
+    Split {
+        into: String,
+        count: usize,
+    },
+    // heals all other enemies in range by amount every interval ticks
+    HealAllies {
+        amount: usize,
+        range: f32,
+        interval: usize,
+    },
+    // walks factor times as fast for duration ticks after every hit
+    SpeedUpWhenHit {
+        factor: f32,
+        duration: usize,
+    },
+}
+
+impl Ability {
+    pub fn name(&self) -> &'static str {
+        return match self {
+            Ability::Armor(_) => "Armor",
+            Ability::Regeneration { .. } => "Regeneration",
+            Ability::Shield(_) => "Shield",
+            Ability::Split { .. } => "Split",
+            Ability::HealAllies { .. } => "Heals allies",
+            Ability::SpeedUpWhenHit { .. } => "Speeds up when hit",
+        };
+    }
+
+    pub fn info(&self) -> String {
+        return match self {
+            Ability::Armor(armor) => format!("Armor {}", armor),
+            Ability::Regeneration { amount, interval } => {
+                format!("Regenerates {} every {}s", amount, *interval as f32 / 60.0)
+            }
+            Ability::Shield(shield) => format!("Shield {}", shield),
+            Ability::Split { into, count } => format!("Splits into {} {}", count, into),
+            Ability::HealAllies {
+                amount,
+                range,
+                interval,
+            } => format!(
+                "Heals allies in range {} by {} every {}s",
+                range,
+                amount,
+                *interval as f32 / 60.0
+            ),
+            Ability::SpeedUpWhenHit { factor, duration } => format!(
+                "Speeds up x{} for {}s when hit",
+                factor,
+                *duration as f32 / 60.0
+            ),
+        };
+    }
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct EnemyArchetype {
+    pub name: String,
+    pub img: ImgID,
+    pub health: usize,
+    pub speed: f32,
+    pub size: f32,
+    pub color: (f32, f32, f32),
+    #[serde(default)]
+    pub abilities: Vec<Ability>,
+}
 
 pub struct Enemy {
-    pub disp: ImgID,
+    pub stats: Rc<EnemyArchetype>,
     pub position: Point,
+    pub last_position: Point,
     pub health: usize,
-    pub walk_speed: f32,
+    pub shield: usize,
     pub next_walk_target: Point,
+    // the tile the enemy is currently walking to
+    pub tile: (usize, usize),
     pub reached_goal: bool,
-    pub color: (f32, f32, f32),
-    pub size: f32,
-    pub debuffs: HashMap<BuffType, Vec<Debuff>>,
+    // ticks since spawning, used to time regeneration and healing
+    pub age: usize,
+    // ticks left of SpeedUpWhenHit
+    pub haste: usize,
+    // the player whose hit killed the enemy, gets the bounty
+    pub killed_by: Option<usize>,
+    pub debuffs: HashMap<DebuffType, Vec<Debuff>>,
 }
 
 impl Enemy {
-    pub fn new(position: Point, spec: &WaveSpec) -> Self {
+    pub fn new(spawn: usize, map: &GameMap, stats: Rc<EnemyArchetype>) -> Self {
+        let tile = map.spawn_points[spawn];
+        return Enemy::at(GameMap::tile_center(tile.0, tile.1), tile, stats);
+    }
+
+    pub fn at(position: Point, tile: (usize, usize), stats: Rc<EnemyArchetype>) -> Self {
+        let mut shield = 0;
+        for ability in stats.abilities.iter() {
+            if let Ability::Shield(amount) = ability {
+                shield += amount;
+            }
+        }
         return Self {
-            disp: spec.img,
+            health: stats.health,
+            stats,
             position,
-            health: spec.health,
+            last_position: position,
+            shield,
             next_walk_target: position,
-            walk_speed: spec.speed,
-            color: spec.color,
-            size: spec.size,
-            debuffs: HashMap::new(),
+            tile,
             reached_goal: false,
+            age: 0,
+            haste: 0,
+            killed_by: None,
+            debuffs: HashMap::new(),
         };
     }
 
     pub fn tick(&mut self, map: &GameMap) {
-        let (new_pos, finished) =
-            move_to(self.position, self.next_walk_target, self.get_walk_speed());
-        self.position = new_pos;
-        if finished {
-            match self.walk_target(map) {
-                Some(next) => self.next_walk_target = next,
-                None => self.reached_goal = true,
+        self.last_position = self.position;
+        let speed = self.get_walk_speed();
+        // stunned enemies stay where they are
+        if speed > 0.0 {
+            let (new_pos, finished) = move_to(self.position, self.next_walk_target, speed);
+            self.position = new_pos;
+            if finished {
+                match self.walk_target(map) {
+                    Some(next) => self.next_walk_target = next,
+                    None => self.reached_goal = true,
+                }
+            }
+        }
+        self.age += 1;
+        self.haste = self.haste.saturating_sub(1);
+        for ability in self.stats.clone().abilities.iter() {
+            if let Ability::Regeneration { amount, interval } = ability {
+                if self.age % interval.max(&1) == 0 {
+                    self.heal(*amount);
+                }
             }
         }
         self.countdown_debuffs();
         for (_, debuffs) in self.debuffs.iter_mut() {
-            debuffs.retain(|debuff| debuff.cooldown > 0);
+            debuffs.retain(|debuff| debuff.duration > 0);
         }
     }
 
+    pub fn heal(&mut self, amount: usize) {
+        if self.health > 0 {
+            self.health = (self.health + amount).min(self.stats.health);
+        }
+    }
+
+    // Applies shields and armor and returns the damage that was actually done
+    pub fn take_damage(&mut self, damage: usize) -> usize {
+        let mut damage = damage * (100 + self.debuff_effect(DebuffType::Vulnerability)) / 100;
+        let shred = self.debuff_effect(DebuffType::ArmorShred);
+        for ability in self.stats.abilities.iter() {
+            match ability {
+                Ability::Armor(armor) => {
+                    damage = damage.saturating_sub(armor.saturating_sub(shred)).max(1)
+                }
+                Ability::SpeedUpWhenHit { duration, .. } => self.haste = *duration,
+                _ => {}
+            }
+        }
+        let absorbed = damage.min(self.shield);
+        self.shield -= absorbed;
+        damage -= absorbed;
+        return self.lose_health(damage);
+    }
+
+    // Takes damage without armor, shields or abilities getting involved
+    pub fn lose_health(&mut self, damage: usize) -> usize {
+        let before = self.health;
+        self.health = self.health.saturating_sub(damage);
+        return before - self.health;
+    }
+
+    // Sum of all active debuffs of that kind, the stacking rules decide how many there are
+    pub fn debuff_effect(&self, kind: DebuffType) -> usize {
+        return match self.debuffs.get(&kind) {
+            Some(debuffs) => debuffs.iter().map(|d| d.effectiveness).sum(),
+            None => 0,
+        };
+    }
+
+    pub fn is_debuffed(&self, kind: DebuffType) -> bool {
+        return self.debuffs.get(&kind).map_or(false, |d| d.len() > 0);
+    }
+
     pub fn countdown_debuffs(&mut self) {
         for (_, debuffs) in self.debuffs.iter_mut() {
             for debuff in debuffs.iter_mut() {
-                debuff.cooldown = debuff.cooldown.saturating_sub(1);
+                debuff.duration = debuff.duration.saturating_sub(1);
+                debuff.elapsed += 1;
             }
         }
     }
 
     pub fn get_walk_speed(&self) -> f32 {
-        if let Some(freeze) = self.debuffs.get(&BuffType::Freeze) {
-            if let Some(max) = freeze.iter().max_by_key(|debuff| debuff.effectiveness) {
-                return self.walk_speed * ((100 - max.effectiveness) as f32 / 100.0);
+        if self.is_debuffed(DebuffType::Stun) {
+            return 0.0;
+        }
+        let mut speed = self.stats.speed;
+        if self.haste > 0 {
+            for ability in self.stats.abilities.iter() {
+                if let Ability::SpeedUpWhenHit { factor, .. } = ability {
+                    speed *= factor;
+                }
             }
         }
-        return self.walk_speed;
+        let slow = self.debuff_effect(DebuffType::Slow).min(100);
+        return speed * ((100 - slow) as f32 / 100.0);
     }
 
-    fn walk_target(&self, map: &GameMap) -> Option<Point> {
-        let (x, y) = GameMap::tile_index_at(self.position);
-        let path = map.path(x, y);
-        return path.get(1).map(|p| GameMap::tile_center(p.x, p.y));
+    fn walk_target(&mut self, map: &GameMap) -> Option<Point> {
+        let (x, y) = map.flow_field.next_tile(self.tile.0, self.tile.1)?;
+        self.tile = (x, y);
+        return Some(GameMap::tile_center(x, y));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    fn enemy(abilities: Vec<Ability>) -> Enemy {
+        let stats = EnemyArchetype {
+            name: "Test".to_string(),
+            img: ImgID::Zombie,
+            health: 100,
+            speed: 1.0,
+            size: 1.0,
+            color: (1.0, 1.0, 1.0),
+            abilities,
+        };
+        return Enemy::at(Point::new(0.0, 0.0), (0, 0), Rc::new(stats));
+    }
+
+    fn map() -> GameMap {
+        return GameMap::new("tutorial", false, &mut StdRng::seed_from_u64(0));
+    }
+
+    #[test]
+    fn armor_reduces_every_hit() {
+        let mut e = enemy(vec![Ability::Armor(5)]);
+        assert_eq!(e.take_damage(12), 7);
+        // but never below 1
+        assert_eq!(e.take_damage(3), 1);
+        assert_eq!(e.health, 92);
+        // poison and the like go right through
+        assert_eq!(e.lose_health(3), 3);
+    }
+
+    #[test]
+    fn shields_absorb_damage_first() {
+        let mut e = enemy(vec![Ability::Armor(5), Ability::Shield(20)]);
+        assert_eq!(e.shield, 20);
+        assert_eq!(e.take_damage(15), 0);
+        assert_eq!(e.shield, 10);
+        assert_eq!(e.take_damage(25), 10);
+        assert_eq!((e.shield, e.health), (0, 90));
+    }
+
+    #[test]
+    fn regeneration_heals_up_to_full_health() {
+        let map = map();
+        let mut e = enemy(vec![Ability::Regeneration {
+            amount: 3,
+            interval: 10,
+        }]);
+        e.lose_health(5);
+        for _ in 0..9 {
+            e.tick(&map);
+        }
+        assert_eq!(e.health, 95);
+        e.tick(&map);
+        assert_eq!(e.health, 98);
+        for _ in 0..10 {
+            e.tick(&map);
+        }
+        assert_eq!(e.health, 100);
+        // the dead stay dead
+        e.lose_health(100);
+        for _ in 0..10 {
+            e.tick(&map);
+        }
+        assert_eq!(e.health, 0);
+    }
+
+    #[test]
+    fn hits_speed_up_for_a_while() {
+        let map = map();
+        let mut e = enemy(vec![Ability::SpeedUpWhenHit {
+            factor: 1.5,
+            duration: 3,
+        }]);
+        assert_eq!(e.get_walk_speed(), 1.0);
+        e.take_damage(1);
+        assert_eq!(e.get_walk_speed(), 1.5);
+        for _ in 0..2 {
+            e.tick(&map);
+        }
+        assert_eq!(e.get_walk_speed(), 1.5);
+        e.tick(&map);
+        assert_eq!(e.get_walk_speed(), 1.0);
     }
 }
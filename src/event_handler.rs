@@ -20,6 +20,7 @@ pub trait GameState {
         keymod: KeyMods,
         repeat: bool,
     ) -> StateTransition;
+    fn mouse_motion_event(&mut self, _ctx: &mut Context, _x: f32, _y: f32) {}
     fn set_data(&mut self, data: Data);
     fn take_data(&mut self) -> Data;
 }
@@ -73,4 +74,8 @@ impl event::EventHandler for GameEventHandler {
             }
         }
     }
+
+    fn mouse_motion_event(&mut self, ctx: &mut Context, x: f32, y: f32, _dx: f32, _dy: f32) {
+        self.state.mouse_motion_event(ctx, x, y);
+    }
 }
@@ -0,0 +1,150 @@
+use crate::map::GameMap;
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashSet};
+use std::usize;
+
+// Distance of every tile to the target and the neighbor to walk to from there.
+// Built with a single Dijkstra run outwards from the target, so enemies can look
+// up their next step in constant time. It has to be rebuilt when the map changes.
+// Blocked tiles (towers in maze mode) are treated like empty ones.
+pub struct FlowField {
+    distance: Vec<Vec<usize>>,
+    next: Vec<Vec<Option<(usize, usize)>>>,
+}
+
+impl FlowField {
+    pub fn empty() -> Self {
+        return Self {
+            distance: vec![],
+            next: vec![],
+        };
+    }
+
+    pub fn new(map: &GameMap, blocked: &HashSet<(usize, usize)>) -> Self {
+        let mut distance = vec![vec![usize::max_value(); map.xsize]; map.ysize];
+        let mut next = vec![vec![None; map.xsize]; map.ysize];
+        let target = map.target().position;
+        let mut frontier = BinaryHeap::new();
+        distance[target.y][target.x] = 0;
+        frontier.push(Reverse((0, target.x, target.y)));
+
+        while let Some(Reverse((cost, x, y))) = frontier.pop() {
+            if cost > distance[y][x] {
+                continue;
+            }
+            // leaving a tile costs as much as the tile itself, same as in GameMap::build_graph
+            for (nx, ny) in map.neighbors(x, y, 1) {
+                if blocked.contains(&(nx, ny)) {
+                    continue;
+                }
+                let new_cost = cost.saturating_add(map.get_tile(nx, ny).cost);
+                if new_cost < distance[ny][nx] {
+                    distance[ny][nx] = new_cost;
+                    next[ny][nx] = Some((x, y));
+                    frontier.push(Reverse((new_cost, nx, ny)));
+                }
+            }
+        }
+        return Self { distance, next };
+    }
+
+    pub fn next_tile(&self, x: usize, y: usize) -> Option<(usize, usize)> {
+        return self.next[y][x];
+    }
+
+    pub fn distance(&self, x: usize, y: usize) -> usize {
+        return self.distance[y][x];
+    }
+
+    pub fn reaches_target(&self, x: usize, y: usize) -> bool {
+        return self.distance(x, y) != usize::max_value();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tile::TileType;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    fn map(level: &str) -> GameMap {
+        return GameMap::new(level, false, &mut StdRng::seed_from_u64(0));
+    }
+
+    fn walkable(map: &GameMap) -> Vec<(usize, usize)> {
+        let mut tiles = vec![];
+        for y in map.yrange() {
+            for x in map.xrange() {
+                if map.get_tile_type(x, y) != TileType::Empty {
+                    tiles.push((x, y));
+                }
+            }
+        }
+        return tiles;
+    }
+
+    // Walks the field from (x, y) and adds up the cost of every tile that is left
+    fn walk(map: &GameMap, field: &FlowField, x: usize, y: usize) -> (usize, Vec<(usize, usize)>) {
+        let mut cost = 0;
+        let mut tiles = vec![(x, y)];
+        let (mut x, mut y) = (x, y);
+        while let Some((nx, ny)) = field.next_tile(x, y) {
+            assert!(map.neighbors(x, y, 1).contains(&(nx, ny)));
+            assert!(field.distance(nx, ny) < field.distance(x, y));
+            cost += map.get_tile(x, y).cost;
+            x = nx;
+            y = ny;
+            tiles.push((x, y));
+        }
+        return (cost, tiles);
+    }
+
+    #[test]
+    fn matches_dijkstra_from_every_tile() {
+        for level in ["tutorial", "switchback"].iter() {
+            let map = map(level);
+            let field = FlowField::new(&map, &HashSet::new());
+            let target = map.target().position;
+            assert_eq!(field.distance(target.x, target.y), 0);
+            assert_eq!(field.next_tile(target.x, target.y), None);
+            for (x, y) in walkable(&map) {
+                let (cost, tiles) = walk(&map, &field, x, y);
+                assert_eq!(*tiles.last().unwrap(), (target.x, target.y));
+                assert_eq!(cost, field.distance(x, y));
+                let path = map.path(x, y);
+                let dijkstra: usize = path[..path.len() - 1]
+                    .iter()
+                    .map(|p| map.get_tile(p.x, p.y).cost)
+                    .sum();
+                assert_eq!(cost, dijkstra, "{} {} {}", level, x, y);
+            }
+        }
+    }
+
+    #[test]
+    fn walks_around_blocked_tiles() {
+        let map = map("arena");
+        let open = FlowField::new(&map, &HashSet::new());
+        let spawn = map.spawn_points[0];
+        let (_, straight) = walk(&map, &open, spawn.0, spawn.1);
+        let blocked: HashSet<(usize, usize)> = straight[2..4].iter().cloned().collect();
+        let field = FlowField::new(&map, &blocked);
+        let (_, around) = walk(&map, &field, spawn.0, spawn.1);
+        assert!(around.iter().all(|tile| !blocked.contains(tile)));
+        assert!(field.distance(spawn.0, spawn.1) > open.distance(spawn.0, spawn.1));
+    }
+
+    #[test]
+    fn walled_off_tiles_do_not_reach_the_target() {
+        let map = map("arena");
+        // a full column of towers in front of the target
+        let wall: HashSet<(usize, usize)> = map.yrange().map(|y| (7, y)).collect();
+        let field = FlowField::new(&map, &wall);
+        let target = map.target().position;
+        assert!(field.reaches_target(target.x, target.y));
+        assert!(!field.reaches_target(0, 0));
+        assert!(!field.reaches_target(map.spawn_points[0].0, map.spawn_points[0].1));
+        assert_eq!(field.next_tile(0, 0), None);
+    }
+}
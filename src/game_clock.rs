@@ -0,0 +1,66 @@
+use ggez::timer;
+use ggez::Context;
+
+pub const TICKS_PER_SECOND: u32 = 60;
+// never run more than this many steps (times the speed) in one update, to avoid
+// fast forwarding through time spent in menus or while the window was stuck
+const MAX_STEPS_PER_UPDATE: usize = 8;
+
+pub struct GameClock {
+    pub speed: u32,
+    pub paused: bool,
+}
+
+impl GameClock {
+    pub fn new() -> Self {
+        return Self {
+            speed: 1,
+            paused: false,
+        };
+    }
+
+    pub fn toggle_pause(&mut self) {
+        self.paused = !self.paused;
+    }
+
+    pub fn cycle_speed(&mut self) {
+        self.speed = match self.speed {
+            1 => 2,
+            2 => 4,
+            _ => 1,
+        };
+    }
+
+    fn ticks_per_second(&self) -> u32 {
+        return TICKS_PER_SECOND * self.speed;
+    }
+
+    // Returns how many simulation steps are due since the last update
+    pub fn due_steps(&self, ctx: &mut Context) -> usize {
+        let mut steps = 0;
+        while timer::check_update_time(ctx, self.ticks_per_second()) {
+            steps += 1;
+        }
+        if self.paused {
+            return 0;
+        }
+        return steps.min(MAX_STEPS_PER_UPDATE * self.speed as usize);
+    }
+
+    // How far the renderer is between the last and the next simulation step, from 0 to 1
+    pub fn interpolation(&self, ctx: &mut Context) -> f32 {
+        if self.paused {
+            return 1.0;
+        }
+        let step = 1.0 / self.ticks_per_second() as f64;
+        let remaining = timer::duration_to_f64(timer::remaining_update_time(ctx));
+        return (remaining / step).min(1.0) as f32;
+    }
+
+    pub fn info(&self) -> String {
+        if self.paused {
+            return "Paused".to_string();
+        }
+        return format!("Speed: {}x", self.speed);
+    }
+}
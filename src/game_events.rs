@@ -0,0 +1,86 @@
+use crate::algebra::Point;
+use crate::assets::ImgID;
+use crate::buffs::BuffType;
+use crate::card::CardType;
+
+// Things that happened in the simulation. Gameplay systems publish them, and
+// everything that reacts to the game without changing it (effects, statistics,
+// ...) consumes them once per tick in PlayingState::dispatch_events.
+#[derive(PartialEq, Clone, Debug)]
+pub enum GameEvent {
+    EnemySpawned {
+        enemy: usize,
+        position: Point,
+    },
+    EnemyHit {
+        enemy: usize,
+        position: Point,
+    },
+    EnemyKilled {
+        enemy: usize,
+        by_tower: Option<usize>,
+        by_player: usize,
+        position: Point,
+    },
+    EnemyLeaked {
+        enemy: usize,
+    },
+    TowerBuilt {
+        tower: usize,
+        x: usize,
+        y: usize,
+    },
+    TowerSold {
+        tower: usize,
+        x: usize,
+        y: usize,
+    },
+    TowerBuffed {
+        tower: usize,
+        x: usize,
+        y: usize,
+        buff: BuffType,
+    },
+    TowerUpgraded {
+        tower: usize,
+        x: usize,
+        y: usize,
+    },
+    // a beam hit its target instantly, there is no projectile to draw
+    BeamFired {
+        from: Point,
+        to: Point,
+        img: ImgID,
+    },
+    CardPlayed {
+        card: CardType,
+    },
+    WaveStarted {
+        wave: usize,
+    },
+    WaveCleared {
+        wave: usize,
+    },
+    GoldEarned {
+        player: usize,
+        gold: usize,
+    },
+}
+
+pub struct GameEvents {
+    queue: Vec<GameEvent>,
+}
+
+impl GameEvents {
+    pub fn new() -> Self {
+        return Self { queue: vec![] };
+    }
+
+    pub fn publish(&mut self, event: GameEvent) {
+        self.queue.push(event);
+    }
+
+    pub fn drain(&mut self) -> Vec<GameEvent> {
+        return self.queue.drain(..).collect();
+    }
+}
@@ -2,15 +2,18 @@ use crate::algebra::{Point, Vector};
 use crate::assets::{Data, ImgID};
 use crate::camera::Camera;
 use crate::card::CardType;
+use crate::command::Command;
+use crate::direction::Dir;
+use crate::hex::Hex;
 use crate::map::GameMap;
 use crate::playing_state::PlayingState;
 use crate::utils::{self, add_mod};
-use crate::wave::WaveStatus;
+use crate::wave::{WaveStatus, PREVIEW_WAVES};
 use ggez::event::{KeyCode, KeyMods};
 use ggez::graphics;
 use ggez::{Context, GameResult};
 
-#[derive(Eq, PartialEq, Hash, Copy, Clone, Debug)]
+#[derive(Eq, PartialEq, Hash, Clone, Debug)]
 pub enum CursorMode {
     Map {
         x: usize,
@@ -23,9 +26,16 @@ pub enum CursorMode {
 
 use self::CursorMode::*;
 
+// Top of the row of cards and actions at the bottom of the screen
+const CARDS_TOP: f32 = 510.0;
+
 pub struct Gui {
     cursor_state: CursorMode,
     camera: Camera,
+    // mouse position on the screen, the wave preview shows details for the group under it
+    pub mouse: Point,
+    // map tile under the mouse, towers can be changed there without holding a card
+    pub hovered: Option<(usize, usize)>,
 }
 
 impl Gui {
@@ -35,6 +45,8 @@ impl Gui {
         return Self {
             cursor_state,
             camera,
+            mouse: Point::new(0.0, 0.0),
+            hovered: None,
         };
     }
 
@@ -42,10 +54,6 @@ impl Gui {
         self.cursor_state = c;
     }
 
-    pub fn chancel(state: &mut PlayingState) {
-        state.gui.set_cursor(CursorMode::Actions(0));
-    }
-
     pub fn move_cursor(state: &mut PlayingState, ix: isize, iy: isize) {
         let len = state.player().deck.hand.len().clone() + state.player().deck.actions.len();
         match state.gui.cursor_state {
@@ -54,8 +62,19 @@ impl Gui {
                 ref mut y,
                 ..
             } => {
-                *y = add_mod(*y, iy, state.map.ysize);
-                *x = add_mod(*x, ix, state.map.xsize);
+                // up and down zig zag between the two upper/lower neighbors to stay in the column
+                let dir = match (ix, iy, *y % 2 == 0) {
+                    (-1, _, _) => Dir::West,
+                    (1, _, _) => Dir::East,
+                    (_, -1, true) => Dir::NorthEast,
+                    (_, -1, false) => Dir::NorthWest,
+                    (_, _, true) => Dir::SouthEast,
+                    (_, _, false) => Dir::SouthWest,
+                };
+                let (nx, ny) = GameMap::tile_direction_neighbor(*x as isize, *y as isize, dir);
+                // wrap around at the borders of the map
+                *x = add_mod(0, nx, state.sim.map.xsize);
+                *y = add_mod(0, ny, state.sim.map.ysize);
             }
             Actions(ref mut slot) => {
                 if len > 0 {
@@ -65,6 +84,41 @@ impl Gui {
         }
     }
 
+    // Remembers the tile under the mouse. The map cursor follows the mouse, outside of the
+    // map it sticks to the closest border tile.
+    pub fn mouse_moved(state: &mut PlayingState, pos: Point) {
+        let world = state.gui.camera.map_pos(pos);
+        let (nx, ny) = state.sim.map.tile_index_at(world);
+        state.gui.hovered = None;
+        if !state.sim.map.valid_tile_pos(nx as isize, ny as isize) {
+            return;
+        }
+        if Hex::from_pixel(world).to_offset() == (nx as isize, ny as isize) {
+            state.gui.hovered = Some((nx, ny));
+        }
+        if let Map {
+            ref mut x,
+            ref mut y,
+            ..
+        } = state.gui.cursor_state
+        {
+            *x = nx;
+            *y = ny;
+        }
+    }
+
+    // The tile tower commands act on, the map cursor while a card is held, otherwise the hovered one
+    pub fn selected_tile(&self) -> Option<(usize, usize)> {
+        if let Map { x, y, .. } = self.cursor_state {
+            return Some((x, y));
+        }
+        return self.hovered;
+    }
+
+    pub fn cursor(&self) -> CursorMode {
+        return self.cursor_state.clone();
+    }
+
     pub fn set_cursor_card_effect(&mut self, slot: usize, c: &CardType) {
         self.set_cursor(CursorMode::Map {
             x: 0,
@@ -108,7 +162,7 @@ impl Gui {
         state: &PlayingState,
         x: usize,
         y: usize,
-        card: CardType,
+        card: &CardType,
         ctx: &mut Context,
     ) -> GameResult<()> {
         let color = if card.is_applicable(state, x, y) {
@@ -122,7 +176,7 @@ impl Gui {
                 .data
                 .as_ref()
                 .unwrap()
-                .get_i(&card.get_preview_image_id()),
+                .get_i(&card.get_preview_image_id(state)),
             graphics::DrawParam::default()
                 .dest(state.gui.camera.world_pos(GameMap::tile_center(x, y)))
                 .offset(Point::new(0.5, 0.5))
@@ -145,7 +199,11 @@ impl Gui {
 
             graphics::draw(
                 ctx,
-                state.data.as_ref().unwrap().get_i(&card.get_image_id()),
+                state
+                    .data
+                    .as_ref()
+                    .unwrap()
+                    .get_i(&card.get_image_id(state)),
                 graphics::DrawParam::default()
                     .dest(Point::new(50.0 + (i as f32) * 80.0, 550.0))
                     .offset(Point::new(0.5, 0.5))
@@ -169,18 +227,24 @@ impl Gui {
         Ok(())
     }
 
-    fn draw_card_info(state: &PlayingState, ctx: &mut Context) -> GameResult<()> {
+    // Returns where the panel ends on the screen
+    fn draw_card_info(state: &PlayingState, ctx: &mut Context) -> GameResult<f32> {
         if let Actions(id) = state.gui.cursor_state {
             if let Some(card) = state.player().deck.get_selected_card(id) {
                 graphics::draw(
                     ctx,
-                    state.data.as_ref().unwrap().get_i(&card.get_image_id()),
+                    state
+                        .data
+                        .as_ref()
+                        .unwrap()
+                        .get_i(&card.get_image_id(state)),
                     graphics::DrawParam::default()
                         .dest(Point::new(600.0, 40.0))
                         .offset(Point::new(0.0, 0.0))
                         .scale(Vector::new(8.0, 8.0)),
                 )?;
-                let mut desc = utils::text(state.data.as_ref().unwrap(), &card.get_description());
+                let mut desc =
+                    utils::text(state.data.as_ref().unwrap(), &card.get_description(state));
                 desc.set_bounds(Point::new(600.0, 400.0), graphics::Align::Left);
                 graphics::draw(
                     ctx,
@@ -190,9 +254,10 @@ impl Gui {
                         .offset(Point::new(0.0, 0.0))
                         .scale(Vector::new(0.3, 0.3)),
                 )?;
+                return Ok(200.0 + desc.height(ctx) as f32 * 0.3);
             }
         }
-        return Ok(());
+        return Ok(0.0);
     }
 
     fn draw_actions(state: &PlayingState, ctx: &mut Context) -> GameResult<()> {
@@ -208,7 +273,11 @@ impl Gui {
 
             graphics::draw(
                 ctx,
-                state.data.as_ref().unwrap().get_i(&card.get_image_id()),
+                state
+                    .data
+                    .as_ref()
+                    .unwrap()
+                    .get_i(&card.get_image_id(state)),
                 graphics::DrawParam::default()
                     .dest(Point::new(750.0 - (i as f32) * 80.0, 550.0))
                     .offset(Point::new(0.5, 0.5))
@@ -261,17 +330,19 @@ impl Gui {
 
     pub fn draw_description(state: &PlayingState, ctx: &mut Context) -> GameResult<()> {
         let mut next_wave = "".to_string();
-        if let WaveStatus::Waiting(time) = state.waves.status {
+        if let WaveStatus::Waiting(time) = state.sim.waves.status {
             next_wave = format!(", Next wave in {}", time / 60);
         }
         let desc = utils::text(
             state.data.as_ref().unwrap(),
             &format!(
-                "Lives: {}, Gold: {} Mana: {}{}",
+                "Lives: {}, Gold: {} Mana: {}{}\nSeed: {}, {}",
                 state.player().hp,
                 state.player().gold,
                 state.player().mana as u64,
-                next_wave
+                next_wave,
+                state.sim.seed,
+                state.clock.info()
             ),
         );
 
@@ -286,18 +357,107 @@ impl Gui {
         return Ok(());
     }
 
+    // Lists the upcoming waves from top down, as far as there is room above the cards
+    fn draw_wave_preview(state: &PlayingState, top: f32, ctx: &mut Context) -> GameResult<()> {
+        let data = state.data.as_ref().unwrap();
+        let mut pos = Point::new(600.0, top.max(250.0));
+        graphics::draw(
+            ctx,
+            &utils::text(data, "Upcoming waves (hover for details)"),
+            graphics::DrawParam::default()
+                .dest(pos)
+                .scale(Vector::new(0.2, 0.2)),
+        )?;
+        pos.y += 30.0;
+        for (id, wave) in state.sim.waves.upcoming(PREVIEW_WAVES) {
+            if pos.y + 25.0 > CARDS_TOP {
+                return Ok(());
+            }
+            graphics::draw(
+                ctx,
+                &utils::text(data, &format!("Wave {}", id + 1)),
+                graphics::DrawParam::default()
+                    .dest(pos)
+                    .scale(Vector::new(0.2, 0.2)),
+            )?;
+            pos.y += 25.0;
+            for group in wave.groups.iter() {
+                let stats = state.sim.enemies.archetype(&group.enemy);
+                let mut info = format!(
+                    "{} x{}  HP {}  Speed {:.1}",
+                    group.enemy,
+                    group.count,
+                    stats.health.saturating_mul(group.health) / 100,
+                    stats.speed * group.speed as f32 / 100.0
+                );
+                let mut details = info.clone();
+                if group.start > 0 {
+                    details += &format!("\nStarts after {}s", group.start as f32 / 60.0);
+                }
+                for ability in stats.abilities.iter() {
+                    details += "\n";
+                    details += &ability.info();
+                }
+                // the hover area covers the expanded text, so moving down over the details keeps them open
+                let height = 20.0 * details.lines().count() as f32 + 5.0;
+                let mouse = state.gui.mouse;
+                if mouse.x >= pos.x && mouse.y >= pos.y && mouse.y < pos.y + height {
+                    info = details;
+                } else if !stats.abilities.is_empty() {
+                    let names: Vec<&str> = stats.abilities.iter().map(|a| a.name()).collect();
+                    info += &format!("\n{}", names.join(", "));
+                }
+                let lines = info.lines().count();
+                if pos.y + 20.0 * lines as f32 > CARDS_TOP {
+                    return Ok(());
+                }
+                let color = stats.color;
+                graphics::draw(
+                    ctx,
+                    data.get_i(&stats.img),
+                    graphics::DrawParam::default()
+                        .dest(pos + Vector::new(10.0, 10.0))
+                        .offset(Point::new(0.5, 0.5))
+                        .scale(Vector::new(2.0 * stats.size, 2.0 * stats.size))
+                        .color(graphics::Color::new(color.0, color.1, color.2, 1.0)),
+                )?;
+                graphics::draw(
+                    ctx,
+                    &utils::text(data, &info),
+                    graphics::DrawParam::default()
+                        .dest(pos + Vector::new(25.0, 0.0))
+                        .scale(Vector::new(0.15, 0.15)),
+                )?;
+                pos.y += 20.0 * lines as f32 + 5.0;
+            }
+        }
+        return Ok(());
+    }
+
+    // Returns where the panel ends on the screen
     pub fn draw_tower_info(
         state: &PlayingState,
         x: usize,
         y: usize,
+        card: Option<&CardType>,
         ctx: &mut Context,
-    ) -> GameResult<()> {
+    ) -> GameResult<f32> {
         let mut info = "".to_string();
-        if let Some(stats) = state.towers.stats_at(x, y) {
+        if let Some(preview) = card.and_then(|c| c.get_preview_info(state, x, y)) {
+            info += &preview;
+            info += "\n";
+        }
+        if let Some(stats) = state.sim.towers.stats_at(x, y) {
             info += &stats.info();
             info += "\n";
         }
-        if let Some(buffs) = state.towers.buffs_at(x, y) {
+        if let Some(tower) = state.sim.towers.get_tower(x, y) {
+            info += &format!("Target: {:?} (T)\n", tower.targeting);
+            for (i, upgrade) in tower.next_upgrades.iter().enumerate() {
+                info += &format!("Upgrade: {} {}g ({})\n", upgrade.name, upgrade.price, i + 1);
+            }
+        }
+        if let Some(buffs) = state.sim.towers.buffs_at(x, y) {
             for buff in buffs.iter() {
                 info += &buff.info();
                 info += "\n";
@@ -312,27 +472,37 @@ impl Gui {
                 .offset(Point::new(0.0, 0.0))
                 .scale(Vector::new(0.3, 0.3)),
         )?;
-        return Ok(());
+        return Ok(50.0 + desc.height(ctx) as f32 * 0.3);
     }
 
     pub fn draw(state: &PlayingState, ctx: &mut Context) -> GameResult<()> {
         Gui::draw_cards(state, ctx)?;
         Gui::draw_actions(state, ctx)?;
 
-        match state.gui.cursor_state {
-            CursorMode::Map { x, y, card, .. } => {
+        // the info panels and the wave preview below them share the right column
+        let info_bottom = match state.gui.cursor_state {
+            CursorMode::Map { x, y, ref card, .. } => {
                 state
                     .gui
                     .draw_map_cursor(x, y, &state.data.as_ref().unwrap(), ctx)?;
                 Gui::draw_effect_preview(state, x, y, card, ctx)?;
-                Gui::draw_tower_info(state, x, y, ctx)?;
+                Gui::draw_tower_info(state, x, y, Some(card), ctx)?
             }
             CursorMode::Actions(slot) => {
                 Gui::draw_cards_cursor(state, slot, &state.data.as_ref().unwrap(), ctx)?;
-                Gui::draw_card_info(state, ctx)?;
+                match state.gui.hovered {
+                    Some((x, y)) if state.sim.towers.get_tower(x, y).is_some() => {
+                        state
+                            .gui
+                            .draw_map_cursor(x, y, &state.data.as_ref().unwrap(), ctx)?;
+                        Gui::draw_tower_info(state, x, y, None, ctx)?
+                    }
+                    _ => Gui::draw_card_info(state, ctx)?,
+                }
             }
-        }
+        };
         Gui::draw_description(state, ctx)?;
+        Gui::draw_wave_preview(state, info_bottom + 10.0, ctx)?;
         Ok(())
     }
 
@@ -342,29 +512,50 @@ impl Gui {
             KeyCode::Down => Gui::move_cursor(state, 0, 1),
             KeyCode::Left => Gui::move_cursor(state, -1, 0),
             KeyCode::Right => Gui::move_cursor(state, 1, 0),
-            KeyCode::Escape => Gui::chancel(state),
+            KeyCode::Escape => {
+                state.execute(Command::Cancel);
+            }
+            KeyCode::N => {
+                state.execute(Command::CallNextWave);
+            }
+            KeyCode::T => {
+                if let Some((x, y)) = state.gui.selected_tile() {
+                    state.execute(Command::CycleTargeting { x, y });
+                }
+            }
+
             KeyCode::Space => match state.gui.cursor_state {
-                CursorMode::Map { x, y, slot, card } => {
-                    Gui::event_activate(state, x, y, slot, card)
+                CursorMode::Map { x, y, .. } => {
+                    state.execute(Command::ActivateAt { x, y });
+                }
+                CursorMode::Actions(slot) => {
+                    state.execute(Command::PlayCard { slot });
                 }
-                CursorMode::Actions(slot) => Gui::event_select(state, slot),
             },
-            _ => {}
-        }
-    }
-
-    fn event_activate(state: &mut PlayingState, x: usize, y: usize, slot: usize, card: CardType) {
-        if card.is_applicable(state, x, y) {
-            card.activate(state, x, y);
-            state.player_mut().deck.card_used(slot);
+            _ => {
+                if let (Some(branch), Some((x, y))) =
+                    (Gui::upgrade_branch(keycode), state.gui.selected_tile())
+                {
+                    state.execute(Command::UpgradeTower { x, y, branch });
+                }
+            }
         }
     }
 
-    fn event_select(state: &mut PlayingState, slot: usize) {
-        if let Some(card) = state.player().deck.get_selected_card(slot) {
-            if card.is_selectable(state, slot) {
-                card.clone().select(state, slot);
-            }
-        }
+    // The number keys buy the upgrades in the order the tower info lists them, towers.ron
+    // has at most MAX_UPGRADE_BRANCHES of them
+    fn upgrade_branch(keycode: KeyCode) -> Option<usize> {
+        let keys = [
+            KeyCode::Key1,
+            KeyCode::Key2,
+            KeyCode::Key3,
+            KeyCode::Key4,
+            KeyCode::Key5,
+            KeyCode::Key6,
+            KeyCode::Key7,
+            KeyCode::Key8,
+            KeyCode::Key9,
+        ];
+        return keys.iter().position(|k| *k == keycode);
     }
 }
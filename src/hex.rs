@@ -0,0 +1,339 @@
+use crate::algebra::{Point, Vector};
+use crate::direction::{Dir, DIRECTIONS};
+
+// Distance between the centers of two neighboring tiles in the same row
+pub const HEX_WIDTH: f32 = 69.0;
+// Distance between two rows of tiles
+pub const HEX_ROW_HEIGHT: f32 = 59.0;
+// Offset from the top left corner of a tile image to the center of the tile
+pub const HEX_CENTER: (f32, f32) = (35.5, 39.5);
+
+// A tile in axial coordinates (pointy top hexes). The third cube coordinate is
+// s = -q - r. The map itself is stored in "odd-r" offset coordinates, where every
+// odd row is shifted half a tile to the right, see Hex::from_offset/to_offset.
+#[derive(Eq, PartialEq, Hash, Copy, Clone, Debug)]
+pub struct Hex {
+    pub q: isize,
+    pub r: isize,
+}
+
+impl Hex {
+    pub fn new(q: isize, r: isize) -> Self {
+        return Self { q, r };
+    }
+
+    pub fn from_cube(x: isize, y: isize, z: isize) -> Self {
+        assert!(x + y + z == 0, "cube coordinates need to sum up to 0");
+        return Self::new(x, z);
+    }
+
+    pub fn s(&self) -> isize {
+        return -self.q - self.r;
+    }
+
+    pub fn to_cube(&self) -> (isize, isize, isize) {
+        return (self.q, self.s(), self.r);
+    }
+
+    pub fn from_offset(x: isize, y: isize) -> Self {
+        return Self::new(x - (y - (y & 1)) / 2, y);
+    }
+
+    pub fn to_offset(&self) -> (isize, isize) {
+        return (self.q + (self.r - (self.r & 1)) / 2, self.r);
+    }
+
+    pub fn add(&self, other: Hex) -> Hex {
+        return Hex::new(self.q + other.q, self.r + other.r);
+    }
+
+    pub fn scale(&self, factor: isize) -> Hex {
+        return Hex::new(self.q * factor, self.r * factor);
+    }
+
+    pub fn direction(dir: Dir) -> Hex {
+        return match dir {
+            Dir::East => Hex::new(1, 0),
+            Dir::NorthEast => Hex::new(1, -1),
+            Dir::NorthWest => Hex::new(0, -1),
+            Dir::West => Hex::new(-1, 0),
+            Dir::SouthWest => Hex::new(-1, 1),
+            Dir::SouthEast => Hex::new(0, 1),
+        };
+    }
+
+    pub fn neighbor(&self, dir: Dir) -> Hex {
+        return self.add(Hex::direction(dir));
+    }
+
+    pub fn distance(&self, other: Hex) -> usize {
+        let (x0, y0, z0) = self.to_cube();
+        let (x1, y1, z1) = other.to_cube();
+        let dx = (x0 - x1).abs();
+        let dy = (y0 - y1).abs();
+        let dz = (z0 - z1).abs();
+        return dx.max(dy).max(dz) as usize;
+    }
+
+    // All tiles with exactly the given distance, starting in the south west and
+    // going around counter clockwise
+    pub fn ring(&self, radius: usize) -> Vec<Hex> {
+        if radius == 0 {
+            return vec![*self];
+        }
+        let mut results = vec![];
+        let mut hex = self.add(Hex::direction(DIRECTIONS[4]).scale(radius as isize));
+        for dir in DIRECTIONS.iter() {
+            for _ in 0..radius {
+                results.push(hex);
+                hex = hex.neighbor(*dir);
+            }
+        }
+        return results;
+    }
+
+    // This tile followed by all rings up to the given radius
+    pub fn spiral(&self, radius: usize) -> Vec<Hex> {
+        let mut results = vec![*self];
+        for i in 1..=radius {
+            results.append(&mut self.ring(i));
+        }
+        return results;
+    }
+
+    // All tiles on the straight line between the two, including both
+    pub fn line_to(&self, other: Hex) -> Vec<Hex> {
+        let n = self.distance(other);
+        // nudge the end points a bit so points on edges always round to the same side
+        let (q0, r0) = (self.q as f32 + 1e-6, self.r as f32 + 1e-6);
+        let (q1, r1) = (other.q as f32 + 1e-6, other.r as f32 + 1e-6);
+        let mut results = vec![];
+        for i in 0..=n {
+            let t = if n == 0 { 0.0 } else { i as f32 / n as f32 };
+            results.push(Hex::round(q0 + (q1 - q0) * t, r0 + (r1 - r0) * t));
+        }
+        return results;
+    }
+
+    // The tile containing fractional axial coordinates
+    pub fn round(q: f32, r: f32) -> Hex {
+        let s = -q - r;
+        let mut rq = q.round();
+        let mut rr = r.round();
+        let mut rs = s.round();
+        let dq = (rq - q).abs();
+        let dr = (rr - r).abs();
+        let ds = (rs - s).abs();
+        // the coordinate that was rounded the most gets recomputed from the others
+        if dq > dr && dq > ds {
+            rq = -rr - rs;
+        } else if dr > ds {
+            rr = -rq - rs;
+        } else {
+            rs = -rq - rr;
+        }
+        return Hex::from_cube(rq as isize, rs as isize, rr as isize);
+    }
+
+    // Center of the tile in world coordinates
+    pub fn to_pixel(&self) -> Point {
+        let x = HEX_WIDTH * (self.q as f32 + self.r as f32 / 2.0);
+        let y = HEX_ROW_HEIGHT * self.r as f32;
+        return Point::new(x, y) + Vector::new(HEX_CENTER.0, HEX_CENTER.1);
+    }
+
+    pub fn from_pixel(point: Point) -> Hex {
+        let r = (point.y - HEX_CENTER.1) / HEX_ROW_HEIGHT;
+        let q = (point.x - HEX_CENTER.0) / HEX_WIDTH - r / 2.0;
+        return Hex::round(q, r);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hexes() -> Vec<Hex> {
+        let mut results = vec![];
+        for y in -5..=5 {
+            for x in -5..=5 {
+                results.push(Hex::from_offset(x, y));
+            }
+        }
+        return results;
+    }
+
+    // The odd-r neighbor arithmetic GameMap used before there was a Hex type
+    fn offset_neighbor(x: isize, y: isize, dir: Dir) -> (isize, isize) {
+        return match (dir, y % 2 == 0) {
+            (Dir::NorthEast, true) => (x, y - 1),
+            (Dir::NorthEast, false) => (x + 1, y - 1),
+            (Dir::SouthWest, true) => (x - 1, y + 1),
+            (Dir::SouthWest, false) => (x, y + 1),
+            (Dir::East, _) => (x + 1, y),
+            (Dir::West, _) => (x - 1, y),
+            (Dir::SouthEast, true) => (x, y + 1),
+            (Dir::SouthEast, false) => (x + 1, y + 1),
+            (Dir::NorthWest, true) => (x - 1, y - 1),
+            (Dir::NorthWest, false) => (x, y - 1),
+        };
+    }
+
+    #[test]
+    fn offset_round_trip() {
+        for y in -5..=5 {
+            for x in -5..=5 {
+                assert_eq!(Hex::from_offset(x, y).to_offset(), (x, y));
+            }
+        }
+        // odd rows are shifted half a tile to the right
+        assert_eq!(Hex::from_offset(0, 0), Hex::new(0, 0));
+        assert_eq!(Hex::from_offset(0, 1), Hex::new(0, 1));
+        assert_eq!(Hex::from_offset(0, 2), Hex::new(-1, 2));
+        assert_eq!(Hex::from_offset(3, 3), Hex::new(2, 3));
+        assert_eq!(Hex::from_offset(0, -1), Hex::new(1, -1));
+    }
+
+    #[test]
+    fn cube_round_trip() {
+        for hex in hexes() {
+            let (x, y, z) = hex.to_cube();
+            assert_eq!(x + y + z, 0);
+            assert_eq!(y, hex.s());
+            assert_eq!(Hex::from_cube(x, y, z), hex);
+        }
+    }
+
+    #[test]
+    #[should_panic]
+    fn invalid_cube() {
+        Hex::from_cube(1, 1, 1);
+    }
+
+    #[test]
+    fn neighbors_match_offset_arithmetic() {
+        for y in -4..=4 {
+            for x in -4..=4 {
+                for dir in DIRECTIONS.iter() {
+                    let hex = Hex::from_offset(x, y).neighbor(*dir);
+                    assert_eq!(
+                        hex.to_offset(),
+                        offset_neighbor(x, y, *dir),
+                        "{} {} {:?}",
+                        x,
+                        y,
+                        dir
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn pixel_round_trip() {
+        for hex in hexes() {
+            assert_eq!(Hex::from_pixel(hex.to_pixel()), hex);
+        }
+        assert_eq!(
+            Hex::new(0, 0).to_pixel(),
+            Point::new(HEX_CENTER.0, HEX_CENTER.1)
+        );
+        assert_eq!(
+            Hex::from_offset(1, 1).to_pixel(),
+            Point::new(
+                HEX_CENTER.0 + 1.5 * HEX_WIDTH,
+                HEX_CENTER.1 + HEX_ROW_HEIGHT
+            )
+        );
+    }
+
+    #[test]
+    fn pixel_near_edges() {
+        for hex in hexes() {
+            let center = hex.to_pixel();
+            for dir in DIRECTIONS.iter() {
+                let neighbor = hex.neighbor(*dir);
+                let towards = neighbor.to_pixel() - center;
+                assert_eq!(Hex::from_pixel(center + towards * 0.49), hex);
+                assert_eq!(Hex::from_pixel(center + towards * 0.51), neighbor);
+            }
+        }
+    }
+
+    #[test]
+    fn distance() {
+        let center = Hex::new(2, -1);
+        assert_eq!(center.distance(center), 0);
+        for dir in DIRECTIONS.iter() {
+            assert_eq!(center.distance(center.neighbor(*dir)), 1);
+            assert_eq!(center.neighbor(*dir).distance(center), 1);
+        }
+        assert_eq!(Hex::new(0, 0).distance(Hex::new(3, -1)), 3);
+        assert_eq!(Hex::new(0, 0).distance(Hex::new(-2, -2)), 4);
+        assert_eq!(Hex::from_offset(0, 0).distance(Hex::from_offset(0, 4)), 4);
+    }
+
+    #[test]
+    fn ring() {
+        let center = Hex::new(1, 2);
+        assert_eq!(center.ring(0), vec![center]);
+        for radius in 1..5 {
+            let ring = center.ring(radius);
+            assert_eq!(ring.len(), 6 * radius);
+            assert_eq!(
+                ring[0],
+                center.add(Hex::direction(Dir::SouthWest).scale(radius as isize))
+            );
+            for (i, hex) in ring.iter().enumerate() {
+                assert_eq!(center.distance(*hex), radius);
+                // every tile is next to the one before
+                assert_eq!(hex.distance(ring[(i + 1) % ring.len()]), 1);
+            }
+        }
+    }
+
+    #[test]
+    fn spiral() {
+        let center = Hex::new(-1, 3);
+        assert_eq!(center.spiral(0), vec![center]);
+        let spiral = center.spiral(3);
+        assert_eq!(spiral.len(), 1 + 6 + 12 + 18);
+        assert_eq!(spiral[0], center);
+        for hex in hexes() {
+            let inside = center.distance(hex) <= 3;
+            assert_eq!(spiral.contains(&hex), inside, "{:?}", hex);
+        }
+        // rings come in order of their distance
+        for pair in spiral.windows(2) {
+            assert!(center.distance(pair[0]) <= center.distance(pair[1]));
+        }
+    }
+
+    #[test]
+    fn line_to() {
+        let start = Hex::new(0, 0);
+        assert_eq!(start.line_to(start), vec![start]);
+        // straight along a direction
+        let east: Vec<Hex> = (0..4).map(|i| Hex::new(i, 0)).collect();
+        assert_eq!(start.line_to(Hex::new(3, 0)), east);
+        let south_west: Vec<Hex> = (0..3).map(|i| Hex::new(-i, i)).collect();
+        assert_eq!(start.line_to(Hex::new(-2, 2)), south_west);
+        for from in hexes().iter().step_by(7) {
+            for to in hexes().iter().step_by(5) {
+                let line = from.line_to(*to);
+                assert_eq!(line.len(), from.distance(*to) + 1);
+                assert_eq!(line[0], *from);
+                assert_eq!(*line.last().unwrap(), *to);
+                // without gaps
+                for pair in line.windows(2) {
+                    assert_eq!(pair[0].distance(pair[1]), 1);
+                }
+            }
+        }
+        // points on an edge between two tiles always go to the same side
+        assert_eq!(
+            start.line_to(Hex::new(1, 1)),
+            vec![start, Hex::new(1, 0), Hex::new(1, 1)]
+        );
+    }
+}
@@ -0,0 +1,93 @@
+use crate::card::CardType;
+use std::collections::HashMap;
+use std::fs;
+
+// Every directory in here is a level, bundling level.ron, map.ron, waves.ron, tower.ron and player.ron
+const LEVELS_PATH: &str = "resources/levels";
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct LevelSettings {
+    // towers block the tiles they stand on and enemies walk around them
+    #[serde(default)]
+    pub maze: bool,
+    // gold for killing an enemy, by archetype name
+    #[serde(default)]
+    pub bounties: HashMap<String, usize>,
+    // gold for every player when a wave is cleared
+    #[serde(default)]
+    pub wave_clear_bonus: usize,
+    #[serde(default)]
+    pub interest: Option<Interest>,
+    // for calling a wave before its countdown ran out
+    #[serde(default)]
+    pub early_call_reward: EarlyCallReward,
+    // percentage of the gold invested into a tower that selling it gives back
+    #[serde(default = "default_sell_refund")]
+    pub sell_refund: usize,
+    // keeps generating waves once waves.ron runs out, the level can't be won
+    #[serde(default)]
+    pub endless: Option<Endless>,
+}
+
+fn default_sell_refund() -> usize {
+    return 75;
+}
+
+// Paid on banked gold whenever a wave is cleared
+#[derive(Debug, Clone, Deserialize)]
+pub struct Interest {
+    pub percent: usize,
+    pub max: usize,
+}
+
+// Paid per second that was left on the countdown
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct EarlyCallReward {
+    #[serde(default)]
+    pub gold: f32,
+    #[serde(default)]
+    pub mana: f32,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Endless {
+    // total enemy health of the first generated wave
+    pub budget: usize,
+    // percent the budget grows with every generated wave
+    pub growth: usize,
+    // archetypes the generated waves are made of
+    pub enemies: Vec<String>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct PlayerSettings {
+    pub hp: usize,
+    pub gold: usize,
+    pub mana: f32,
+    pub hand: Vec<CardType>,
+    pub deck: Vec<CardType>,
+    pub actions: Vec<CardType>,
+}
+
+pub fn level_path(level: &str, name: &str) -> String {
+    return format!("{}/{}/{}.ron", LEVELS_PATH, level, name);
+}
+
+// Level ids in alphabetical order
+pub fn available_levels() -> Vec<String> {
+    let entries = fs::read_dir(LEVELS_PATH).expect("Failed reading levels directory");
+    let mut levels: Vec<String> = entries
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| entry.path().is_dir())
+        .map(|entry| entry.file_name().to_string_lossy().to_string())
+        .collect();
+    levels.sort();
+    return levels;
+}
+
+pub fn default_level() -> String {
+    return available_levels()
+        .into_iter()
+        .next()
+        .expect("There are no levels in resources/levels");
+}
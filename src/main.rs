@@ -20,6 +20,7 @@ mod buffs;
 mod camera;
 mod card;
 mod card_deck;
+mod command;
 mod debuffs;
 mod dijkstra;
 mod direction;
@@ -28,15 +29,26 @@ mod end_state;
 mod enemies;
 mod enemy;
 mod event_handler;
+mod flow_field;
+mod game_clock;
+mod game_events;
 mod gui;
+mod hex;
+mod level;
 mod map;
 mod menu_state;
 mod overlay_state;
+mod path_benchmark;
 mod pile_overlay;
 mod player;
 mod playing_state;
 mod projectiles;
+mod replay;
+mod save_game;
 mod shop_overlay;
+mod sim_rng;
+mod simulation;
+mod statistics;
 mod tile;
 mod tower;
 mod tower_stats;
@@ -46,8 +58,57 @@ mod wave;
 use crate::assets::Data;
 use crate::event_handler::GameState;
 use crate::menu_state::MenuState;
+use crate::playing_state::PlayingState;
+use crate::replay::Replay;
+use crate::simulation::{Outcome, Simulation};
+
+fn arg_value(name: &str) -> Option<String> {
+    let args = env::args().collect::<Vec<_>>();
+    let pos = args.iter().position(|arg| arg == name)?;
+    let value = args.get(pos + 1).expect(&format!("{} needs a value", name));
+    return Some(value.clone());
+}
+
+fn seed_from_args() -> Option<u64> {
+    let seed = arg_value("--seed")?;
+    return Some(seed.parse().expect("--seed needs to be a number"));
+}
+
+fn run_headless(level: &str, seed: u64) {
+    let mut sim = Simulation::new(level, false, seed);
+    let outcome = sim.run(60 * 60 * 60);
+    print_outcome(outcome, sim.time());
+}
+
+fn run_headless_replay(path: &str) {
+    let mut state = PlayingState::replaying(Replay::load(path));
+    let outcome = state.run(60 * 60 * 60);
+    print_outcome(outcome, state.time());
+}
+
+fn print_outcome(outcome: Option<Outcome>, time: f32) {
+    match outcome {
+        Some(outcome) => println!("Game ended after {} ticks: {:?}", time, outcome),
+        None => println!("Game still running after {} ticks", time),
+    }
+}
 
 pub fn main() {
+    let seed = seed_from_args();
+    if env::args().any(|arg| arg == "--bench-paths") {
+        let level = arg_value("--level").unwrap_or_else(level::default_level);
+        path_benchmark::run(&level, 90);
+        return;
+    }
+    if env::args().any(|arg| arg == "--headless") {
+        if let Some(path) = arg_value("--replay") {
+            run_headless_replay(&path);
+            return;
+        }
+        let level = arg_value("--level").unwrap_or_else(level::default_level);
+        run_headless(&level, seed.unwrap_or_else(rand::random));
+        return;
+    }
     let c = conf::Conf::new();
     let manifest_dir = env::var("CARGO_MANIFEST_DIR").unwrap();
     let mut path = path::PathBuf::from(manifest_dir);
@@ -61,7 +122,10 @@ pub fn main() {
     let mut data = Data::new();
     data.init(&mut ctx).expect("couldn't load resources");
 
-    let mut init_state = Box::new(MenuState::new());
+    let mut init_state: Box<GameState> = match arg_value("--replay") {
+        Some(path) => Box::new(PlayingState::replaying(Replay::load(&path))),
+        None => Box::new(MenuState::new(seed, arg_value("--record"))),
+    };
     init_state.set_data(data);
 
     let events = &mut event_handler::GameEventHandler::new(init_state);
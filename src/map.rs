@@ -2,14 +2,16 @@ use crate::algebra::{Point, Position, Vector};
 use crate::assets::{Data, ImgID};
 use crate::dijkstra::{reconstruct_path, shortest_path, Edge};
 use crate::direction::{Dir, DIRECTIONS};
+use crate::flow_field::FlowField;
+use crate::hex::{Hex, HEX_CENTER};
 use crate::playing_state::PlayingState;
 use crate::tile::TileType::*;
 use crate::tile::{Tile, TileType};
-use crate::utils::{distance, load_specs};
+use crate::utils::load_level_specs;
 use ggez::graphics::{draw, DrawParam};
 use ggez::{Context, GameResult};
 use rand::prelude::*;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::f32;
 use std::ops::Range;
 
@@ -24,11 +26,16 @@ pub struct GameMap {
     pub data: Vec<Vec<Tile>>,
     decorations: Vec<Decoration>,
     images: HashMap<TileType, ImgID>,
+    // spawn tiles in reading order of map.ron
+    pub spawn_points: Vec<(usize, usize)>,
+    pub flow_field: FlowField,
+    pub maze: bool,
+    blocked: HashSet<(usize, usize)>,
 }
 
 impl GameMap {
-    pub fn new() -> Self {
-        let tiletypes = load_specs::<Vec<TileType>>("map");
+    pub fn new<R: Rng>(level: &str, maze: bool, rng: &mut R) -> Self {
+        let tiletypes = load_level_specs::<Vec<Vec<TileType>>>(level, "map");
         let xsize = tiletypes[0].len();
         let ysize = tiletypes.len();
         let data = tiletypes
@@ -38,7 +45,14 @@ impl GameMap {
                 outer
                     .iter()
                     .enumerate()
-                    .map(|(x, kind)| Tile::new_from_type(*kind, x, y))
+                    .map(|(x, kind)| {
+                        let mut tile = Tile::new_from_type(*kind, x, y);
+                        // in a maze every free build tile is part of the path
+                        if maze && *kind == Build {
+                            tile.cost = 1;
+                        }
+                        tile
+                    })
                     .collect()
             })
             .collect();
@@ -56,13 +70,18 @@ impl GameMap {
             xsize,
             ysize,
             images,
+            spawn_points: vec![],
+            flow_field: FlowField::empty(),
+            maze,
+            blocked: HashSet::new(),
         };
-        res.create_decorations();
-        res.path(4, 4);
+        res.create_decorations(rng);
+        res.spawn_points = res.get_spawn_points();
+        res.update_flow_field();
         return res;
     }
 
-    pub fn create_decorations(&mut self) {
+    pub fn create_decorations<R: Rng>(&mut self, rng: &mut R) {
         let decoration_build = vec![ImgID::Tree1, ImgID::Tree2, ImgID::Tree3];
         let decoration_walk = vec![
             ImgID::Stone(1),
@@ -76,32 +95,26 @@ impl GameMap {
             for y in self.yrange() {
                 match self.get_tile_type(x, y) {
                     Build => {
-                        if rand::thread_rng().gen::<f32>() > 0.1 {
-                            let offset =
-                                (Vector::new(rand::thread_rng().gen(), rand::thread_rng().gen())
-                                    * 60.0)
-                                    - Vector::new(30.0, 30.0);
+                        if rng.gen::<f32>() > 0.1 {
+                            let offset = (Vector::new(rng.gen(), rng.gen()) * 60.0)
+                                - Vector::new(30.0, 30.0);
                             let pos = GameMap::tile_center(x, y) + offset;
                             self.decorations.push(Decoration {
                                 pos,
-                                disp: decoration_build
-                                    [rand::thread_rng().gen::<usize>() % decoration_build.len()],
+                                disp: decoration_build[rng.gen::<usize>() % decoration_build.len()],
                             });
                         }
                     }
                     Walk(_) => {
                         for _i in 1..4 {
-                            if rand::thread_rng().gen::<f32>() > 0.1 {
-                                let offset = (Vector::new(
-                                    rand::thread_rng().gen(),
-                                    rand::thread_rng().gen(),
-                                ) * 70.0)
+                            if rng.gen::<f32>() > 0.1 {
+                                let offset = (Vector::new(rng.gen(), rng.gen()) * 70.0)
                                     - Vector::new(35.0, 30.0);
                                 let pos = GameMap::tile_center(x, y) + offset;
                                 self.decorations.push(Decoration {
                                     pos,
                                     disp: decoration_walk
-                                        [rand::thread_rng().gen::<usize>() % decoration_walk.len()],
+                                        [rng.gen::<usize>() % decoration_walk.len()],
                                 });
                             }
                         }
@@ -124,26 +137,6 @@ impl GameMap {
         return false;
     }
 
-    pub fn tile_ring(x_in: isize, y_in: isize, radius: usize) -> Vec<(isize, isize)> {
-        let mut results = vec![];
-        let mut x = x_in;
-        let mut y = y_in;
-        for _ in 0..radius {
-            let (x_i, y_i) = GameMap::tile_direction_neighbor(x, y, DIRECTIONS[4]);
-            x = x_i;
-            y = y_i;
-        }
-        for i in 0..6 {
-            for _ in 0..radius {
-                results.push((x, y));
-                let (x_i, y_i) = GameMap::tile_direction_neighbor(x, y, DIRECTIONS[i]);
-                x = x_i;
-                y = y_i;
-            }
-        }
-        return results;
-    }
-
     pub fn neighbors(&self, x: usize, y: usize, radius: usize) -> Vec<(usize, usize)> {
         let mut potential = GameMap::tile_potential_neighbors(x as isize, y as isize, radius);
         potential.retain(|(x, y)| self.valid_tile_pos(*x, *y));
@@ -154,57 +147,32 @@ impl GameMap {
     }
 
     pub fn tile_potential_neighbors(x: isize, y: isize, radius: usize) -> Vec<(isize, isize)> {
-        let mut results = vec![];
-        for i in 1..=radius {
-            results.append(&mut GameMap::tile_ring(x, y, i));
-        }
-        return results;
+        return Hex::from_offset(x, y)
+            .spiral(radius)
+            .iter()
+            .skip(1)
+            .map(|hex| hex.to_offset())
+            .collect();
     }
 
     pub fn tile_direction_neighbor(x: isize, y: isize, dir: Dir) -> (isize, isize) {
-        return match (dir, y % 2 == 0) {
-            (Dir::NorthEast, true) => (x, y - 1),
-            (Dir::NorthEast, false) => (x + 1, y - 1),
-            (Dir::SouthWest, true) => (x - 1, y + 1),
-            (Dir::SouthWest, false) => (x, y + 1),
-            (Dir::East, _) => (x + 1, y),
-            (Dir::West, _) => (x - 1, y),
-            (Dir::SouthEast, true) => (x, y + 1),
-            (Dir::SouthEast, false) => (x + 1, y + 1),
-            (Dir::NorthWest, true) => (x - 1, y - 1),
-            (Dir::NorthWest, false) => (x, y - 1),
-        };
+        return Hex::from_offset(x, y).neighbor(dir).to_offset();
     }
 
     pub fn tile_pos(x: usize, y: usize) -> Point {
-        if y % 2 == 0 {
-            return Point::new(69.0 * x as f32, 59.0 * y as f32);
-        } else {
-            return Point::new(35.0 + 69.0 * x as f32, 59.0 * y as f32);
-        }
+        return GameMap::tile_center(x, y) - Vector::new(HEX_CENTER.0, HEX_CENTER.1);
     }
 
     pub fn tile_center(x: usize, y: usize) -> Point {
-        return GameMap::tile_pos(x, y) + Vector::new(35.5, 39.5);
+        return Hex::from_offset(x as isize, y as isize).to_pixel();
     }
 
-    pub fn tile_index_at(point: Point) -> (usize, usize) {
-        let x = (point.x / 69.0) as usize;
-        let y = (point.y / 59.0) as usize;
-        let mut min_distance = f32::INFINITY;
-        let mut rx = 0;
-        let mut ry = 0;
-        for xi in x.saturating_sub(1)..x + 1 {
-            for yi in y.saturating_sub(1)..y + 1 {
-                let distance = distance(&GameMap::tile_center(xi, yi), &point);
-                if min_distance > distance {
-                    min_distance = distance;
-                    rx = xi;
-                    ry = yi;
-                }
-            }
-        }
-        return (rx as usize, ry as usize);
+    // Points outside of the map are clamped to the closest row and column
+    pub fn tile_index_at(&self, point: Point) -> (usize, usize) {
+        let (x, y) = Hex::from_pixel(point).to_offset();
+        let x = x.max(0).min(self.xsize as isize - 1);
+        let y = y.max(0).min(self.ysize as isize - 1);
+        return (x as usize, y as usize);
     }
 
     pub fn get_tile_type(&self, x: usize, y: usize) -> TileType {
@@ -215,11 +183,6 @@ impl GameMap {
         return &self.data[y][x];
     }
 
-    pub fn tile_at(&self, point: Point) -> TileType {
-        let (x, y) = GameMap::tile_index_at(point);
-        return self.get_tile_type(x, y);
-    }
-
     pub fn xrange(&self) -> Range<usize> {
         return 0..self.xsize;
     }
@@ -244,8 +207,8 @@ impl GameMap {
 
     pub fn get_spawn_points(&self) -> Vec<(usize, usize)> {
         let mut spawns = Vec::new();
-        for x in self.xrange() {
-            for y in self.yrange() {
+        for y in self.yrange() {
+            for x in self.xrange() {
                 if self.is_spawn(x, y) {
                     spawns.push((x, y))
                 }
@@ -254,10 +217,48 @@ impl GameMap {
         return spawns;
     }
 
+    // Has to be called whenever tiles change, so enemies walk around them
+    pub fn update_flow_field(&mut self) {
+        self.flow_field = FlowField::new(self, &self.blocked);
+    }
+
+    pub fn place_tower(&mut self, x: usize, y: usize) {
+        if self.maze {
+            self.blocked.insert((x, y));
+            self.update_flow_field();
+        }
+    }
+
+    pub fn remove_tower(&mut self, x: usize, y: usize) {
+        if self.maze && self.blocked.remove(&(x, y)) {
+            self.update_flow_field();
+        }
+    }
+
+    // Whether all spawn points and the given tiles (where enemies are walking to)
+    // still reach the target with a tower on (x, y). Always true outside of maze
+    // mode, where towers never block.
+    pub fn keeps_paths_open(&self, x: usize, y: usize, tiles: &[(usize, usize)]) -> bool {
+        if !self.maze {
+            return true;
+        }
+        if tiles.contains(&(x, y)) {
+            return false;
+        }
+        let mut blocked = self.blocked.clone();
+        blocked.insert((x, y));
+        let field = FlowField::new(self, &blocked);
+        return self
+            .spawn_points
+            .iter()
+            .chain(tiles.iter())
+            .all(|(tx, ty)| field.reaches_target(*tx, *ty));
+    }
+
     pub fn draw(state: &PlayingState, data: &Data, ctx: &mut Context) -> GameResult<()> {
-        for y in state.map.yrange() {
-            for x in state.map.xrange() {
-                let tiletype = state.map.get_tile_type(x, y);
+        for y in state.sim.map.yrange() {
+            for x in state.sim.map.xrange() {
+                let tiletype = state.sim.map.get_tile_type(x, y);
                 if tiletype != Empty {
                     draw(
                         ctx,
@@ -268,9 +269,9 @@ impl GameMap {
                 }
             }
         }
-        for y in state.map.yrange() {
-            for x in state.map.xrange() {
-                let tiletype = state.map.get_tile_type(x, y);
+        for y in state.sim.map.yrange() {
+            for x in state.sim.map.xrange() {
+                let tiletype = state.sim.map.get_tile_type(x, y);
                 if tiletype != Empty {
                     draw(
                         ctx,
@@ -280,7 +281,7 @@ impl GameMap {
                     )?;
                     draw(
                         ctx,
-                        data.get_i(&state.map.images[&tiletype]),
+                        data.get_i(&state.sim.map.images[&tiletype]),
                         DrawParam::default()
                             .dest(state.gui.cam().world_pos(GameMap::tile_pos(x, y))),
                     )?;
@@ -288,7 +289,7 @@ impl GameMap {
             }
         }
 
-        for dec in state.map.decorations.iter() {
+        for dec in state.sim.map.decorations.iter() {
             draw(
                 ctx,
                 data.get_i(&dec.disp),
@@ -341,3 +342,66 @@ impl GameMap {
         return path;
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::rngs::StdRng;
+
+    fn arena(maze: bool) -> GameMap {
+        return GameMap::new("arena", maze, &mut StdRng::seed_from_u64(0));
+    }
+
+    #[test]
+    fn towers_only_block_in_maze_mode() {
+        let mut map = arena(false);
+        let (sx, sy) = map.spawn_points[0];
+        let distance = map.flow_field.distance(sx, sy);
+        for y in map.yrange() {
+            map.place_tower(7, y);
+        }
+        assert_eq!(map.flow_field.distance(sx, sy), distance);
+        assert!(map.keeps_paths_open(sx + 1, sy, &[(sx + 1, sy)]));
+    }
+
+    #[test]
+    fn enemies_repath_around_towers() {
+        let mut map = arena(true);
+        let (sx, sy) = map.spawn_points[0];
+        let distance = map.flow_field.distance(sx, sy);
+        let next = map.flow_field.next_tile(sx, sy).unwrap();
+        map.place_tower(next.0, next.1);
+        assert_ne!(map.flow_field.next_tile(sx, sy), Some(next));
+        assert!(map.flow_field.distance(sx, sy) >= distance);
+        map.remove_tower(next.0, next.1);
+        assert_eq!(map.flow_field.next_tile(sx, sy), Some(next));
+        assert_eq!(map.flow_field.distance(sx, sy), distance);
+    }
+
+    #[test]
+    fn the_last_gap_stays_open() {
+        let mut map = arena(true);
+        for y in 0..map.ysize - 1 {
+            assert!(map.keeps_paths_open(7, y, &[]));
+            map.place_tower(7, y);
+        }
+        let gap = (7, map.ysize - 1);
+        assert!(!map.keeps_paths_open(gap.0, gap.1, &[]));
+        // towers elsewhere are fine
+        assert!(map.keeps_paths_open(3, 0, &[]));
+        // but not where an enemy stands
+        assert!(!map.keeps_paths_open(3, 0, &[(3, 0)]));
+    }
+
+    #[test]
+    fn enemies_are_not_walled_in() {
+        let mut map = arena(true);
+        let mut exits = map.neighbors(0, 0, 1);
+        let (x, y) = exits.pop().unwrap();
+        for (nx, ny) in exits {
+            map.place_tower(nx, ny);
+        }
+        assert!(map.keeps_paths_open(x, y, &[]));
+        assert!(!map.keeps_paths_open(x, y, &[(0, 0)]));
+    }
+}
@@ -1,14 +1,18 @@
 use crate::algebra::{Point, Vector};
 use crate::assets::Data;
 use crate::event_handler::{self, StateTransition};
+use crate::level;
 use crate::playing_state::PlayingState;
+use crate::save_game::SaveGame;
 use crate::utils::{self, add_mod};
 use ggez::event::{KeyCode, KeyMods};
 use ggez::graphics::{self, Color};
 use ggez::{Context, GameResult};
 
 pub enum MenuItem {
-    Debug,
+    Continue,
+    // the level to start with every card, picked with left and right
+    Debug(String),
     Level(String),
     Exit,
 }
@@ -17,7 +21,8 @@ impl MenuItem {
     fn get_text(&self) -> String {
         match self {
             MenuItem::Level(a) => format!("Play level: {}", &a),
-            MenuItem::Debug => "Debug".to_string(),
+            MenuItem::Continue => "Continue saved game".to_string(),
+            MenuItem::Debug(level) => format!("Debug level: < {} >", &level),
             MenuItem::Exit => "Exit".to_string(),
         }
     }
@@ -26,22 +31,53 @@ impl MenuItem {
 pub struct MenuState {
     option_selected: usize,
     options: Vec<MenuItem>,
+    seed: Option<u64>,
+    record: Option<String>,
+    // shown below the menu, e.g. when the save game couldn't be loaded
+    error: Option<String>,
     data: Option<Data>,
 }
 
 impl MenuState {
-    pub fn new() -> Self {
-        let options = vec![
-            MenuItem::Level("Play".to_string()),
-            MenuItem::Debug,
-            MenuItem::Exit,
-        ];
+    pub fn new(seed: Option<u64>, record: Option<String>) -> Self {
+        let mut options: Vec<MenuItem> = level::available_levels()
+            .into_iter()
+            .map(MenuItem::Level)
+            .collect();
+        options.push(MenuItem::Debug(level::default_level()));
+        options.push(MenuItem::Exit);
+        if SaveGame::exists() {
+            options.insert(0, MenuItem::Continue);
+        }
         return Self {
             option_selected: 0,
             options,
+            seed,
+            record,
+            error: None,
             data: None,
         };
     }
+
+    fn cycle_debug_level(&mut self, op: isize) {
+        if let MenuItem::Debug(level) = &mut self.options[self.option_selected] {
+            let levels = level::available_levels();
+            let current = levels.iter().position(|l| l == level).unwrap_or(0);
+            *level = levels[add_mod(current, op, levels.len())].clone();
+        }
+    }
+
+    fn game_seed(&self) -> u64 {
+        return self.seed.unwrap_or_else(rand::random);
+    }
+
+    fn start_game(&self, level: &str, debug: bool) -> PlayingState {
+        let seed = self.game_seed();
+        return match &self.record {
+            Some(path) => PlayingState::recording(level, debug, seed, path.clone()),
+            None => PlayingState::new(level, debug, seed),
+        };
+    }
 }
 
 impl event_handler::GameState for MenuState {
@@ -69,6 +105,17 @@ impl event_handler::GameState for MenuState {
                     .color(color),
             )?;
         }
+        if let Some(error) = &self.error {
+            let desc = utils::text(self.data.as_ref().unwrap(), error);
+            graphics::draw(
+                ctx,
+                &desc,
+                graphics::DrawParam::default()
+                    .dest(Point::new(300.0, 100.0 + 40.0 * self.options.len() as f32))
+                    .scale(Vector::new(0.2, 0.2))
+                    .color(Color::new(1.0, 0.3, 0.3, 1.0)),
+            )?;
+        }
         graphics::present(ctx)?;
         Ok(())
     }
@@ -87,13 +134,25 @@ impl event_handler::GameState for MenuState {
             KeyCode::Down => {
                 self.option_selected = add_mod(self.option_selected, 1, self.options.len())
             }
+            KeyCode::Left => self.cycle_debug_level(-1),
+            KeyCode::Right => self.cycle_debug_level(1),
             KeyCode::Space => {
                 match &self.options[self.option_selected] {
-                    MenuItem::Level(a) => {
-                        return StateTransition::Next(Box::new(PlayingState::new(false)));
+                    MenuItem::Level(level) => {
+                        return StateTransition::Next(Box::new(self.start_game(level, false)));
                     }
-                    MenuItem::Debug => {
-                        return StateTransition::Next(Box::new(PlayingState::new(true)));
+                    MenuItem::Continue => match SaveGame::load().and_then(PlayingState::resume) {
+                        Ok(state) => {
+                            return StateTransition::Next(Box::new(state));
+                        }
+                        Err(e) => {
+                            self.error = Some(e);
+                            self.options.remove(self.option_selected);
+                            self.option_selected = 0;
+                        }
+                    },
+                    MenuItem::Debug(level) => {
+                        return StateTransition::Next(Box::new(self.start_game(level, true)));
                     }
                     MenuItem::Exit => return StateTransition::Exit,
                 };
@@ -0,0 +1,57 @@
+use crate::map::GameMap;
+use rand::rngs::StdRng;
+use rand::SeedableRng;
+use std::time::{Duration, Instant};
+
+// Walks a wave of enemies from the spawn points to the target, once with a
+// Dijkstra run per step (the way enemies used to look up their next tile) and
+// once with flow field lookups, and prints how long both took.
+pub fn run(level: &str, enemies: usize) {
+    let mut rng = StdRng::seed_from_u64(0);
+    let mut map = GameMap::new(level, false, &mut rng);
+
+    let start = Instant::now();
+    let mut dijkstra_steps = 0;
+    for i in 0..enemies {
+        let (mut x, mut y) = map.spawn_points[i % map.spawn_points.len()];
+        while let Some(next) = map.path(x, y).get(1) {
+            x = next.x;
+            y = next.y;
+            dijkstra_steps += 1;
+        }
+    }
+    let dijkstra = start.elapsed();
+
+    let start = Instant::now();
+    map.update_flow_field();
+    let rebuild = start.elapsed();
+    let mut flow_steps = 0;
+    for i in 0..enemies {
+        let (mut x, mut y) = map.spawn_points[i % map.spawn_points.len()];
+        while let Some(next) = map.flow_field.next_tile(x, y) {
+            x = next.0;
+            y = next.1;
+            flow_steps += 1;
+        }
+    }
+    let flow = start.elapsed();
+
+    println!("Level {}, {} enemies", level, enemies);
+    println!(
+        "Dijkstra per step:   {:?} for {} steps ({:?} per step)",
+        dijkstra,
+        dijkstra_steps,
+        per_step(dijkstra, dijkstra_steps)
+    );
+    println!(
+        "Flow field:          {:?} for {} steps ({:?} per step, {:?} of it rebuilding the field)",
+        flow,
+        flow_steps,
+        per_step(flow, flow_steps),
+        rebuild
+    );
+}
+
+fn per_step(time: Duration, steps: usize) -> Duration {
+    return time / steps.max(1) as u32;
+}
@@ -10,7 +10,8 @@ use crate::utils::{self, add_mod};
 use ggez::event::{KeyCode, KeyMods};
 use ggez::graphics::{self, Color};
 use ggez::{Context, GameResult};
-use rand::{thread_rng, Rng};
+use rand::seq::SliceRandom;
+use rand::Rng;
 
 pub struct PileOverlay {
     cur_selected: usize,
@@ -18,9 +19,9 @@ pub struct PileOverlay {
 }
 
 impl PileOverlay {
-    pub fn new(cards: Vec<CardType>) -> Self {
+    pub fn new<R: Rng>(cards: Vec<CardType>, rng: &mut R) -> Self {
         let mut shuffled = cards.clone();
-        thread_rng().shuffle(&mut shuffled);
+        shuffled.as_mut_slice().shuffle(rng);
         return Self {
             cur_selected: 0,
             cards: shuffled,
@@ -49,7 +50,11 @@ impl PileOverlay {
             )?;
             graphics::draw(
                 ctx,
-                state.data.as_ref().unwrap().get_i(&card.get_image_id()),
+                state
+                    .data
+                    .as_ref()
+                    .unwrap()
+                    .get_i(&card.get_image_id(state)),
                 graphics::DrawParam::default()
                     .dest(Point::new(
                         100.0,
@@ -99,13 +104,17 @@ impl PileOverlay {
         let card = self.cards.get(self.cur_selected).unwrap_or(return Ok(()));
         graphics::draw(
             ctx,
-            state.data.as_ref().unwrap().get_i(&card.get_image_id()),
+            state
+                .data
+                .as_ref()
+                .unwrap()
+                .get_i(&card.get_image_id(state)),
             graphics::DrawParam::default()
                 .dest(Point::new(300.0, 40.0))
                 .offset(Point::new(0.0, 0.0))
                 .scale(Vector::new(8.0, 8.0)),
         )?;
-        let desc = utils::text(state.data.as_ref().unwrap(), &card.get_description());
+        let desc = utils::text(state.data.as_ref().unwrap(), &card.get_description(state));
         graphics::draw(
             ctx,
             &desc,
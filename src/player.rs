@@ -1,5 +1,8 @@
+use crate::card::CardSpecs;
 use crate::card_deck::CardDeck;
-use crate::playing_state::PlayingState;
+use crate::level::PlayerSettings;
+use crate::simulation::Simulation;
+use rand::Rng;
 
 pub struct Player {
     pub id: usize,
@@ -10,13 +13,13 @@ pub struct Player {
 }
 
 impl Player {
-    pub fn new(id: usize) -> Self {
-        let hp = 10;
-        let gold = 300;
-        let mana = 3.0;
-        let mut deck = CardDeck::new();
-        deck.shuffle();
-        deck.draw(5);
+    pub fn new<R: Rng>(id: usize, settings: &PlayerSettings, rng: &mut R) -> Self {
+        let hp = settings.hp;
+        let gold = settings.gold;
+        let mana = settings.mana;
+        let mut deck = CardDeck::new(settings);
+        deck.shuffle(rng);
+        deck.draw(5, rng);
         Self {
             id,
             deck,
@@ -26,13 +29,13 @@ impl Player {
         }
     }
 
-    pub fn debug(id: usize) -> Self {
+    pub fn debug<R: Rng>(id: usize, cards: &CardSpecs, rng: &mut R) -> Self {
         let hp = 1337;
         let gold = 9001;
         let mana = 100.0;
-        let mut deck = CardDeck::all();
-        deck.shuffle();
-        deck.draw(5);
+        let mut deck = CardDeck::all(cards);
+        deck.shuffle(rng);
+        deck.draw(5, rng);
         Self {
             id,
             deck,
@@ -42,7 +45,7 @@ impl Player {
         }
     }
 
-    pub fn tick(state: &mut PlayingState) {
-        state.player_mut().mana += 0.005;
+    pub fn tick(sim: &mut Simulation) {
+        sim.player_mut().mana += 0.005;
     }
 }
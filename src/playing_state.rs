@@ -1,89 +1,151 @@
+use crate::algebra::Point;
 use crate::assets::Data;
 use crate::background::Background;
+use crate::command::Command;
 use crate::effects::Effects;
 use crate::end_state::EndState;
 use crate::enemies::Enemies;
 use crate::event_handler::{self, StateTransition};
+use crate::game_clock::GameClock;
 use crate::gui::Gui;
 use crate::map::GameMap;
+use crate::menu_state::MenuState;
 use crate::overlay_state::OverlayState;
 use crate::player::Player;
 use crate::projectiles::Projectiles;
+use crate::replay::{Replay, ReplayMode};
+use crate::save_game::SaveGame;
+use crate::simulation::{Outcome, Simulation};
+use crate::statistics::Statistics;
 use crate::towers::Towers;
-use crate::wave::{WaveStatus, Waves};
 use ggez::event::{KeyCode, KeyMods};
 use ggez::graphics::{self, Color};
 use ggez::{Context, GameResult};
-use std::collections::HashMap;
+use rand::rngs::StdRng;
+use rand::SeedableRng;
 
 pub struct PlayingState {
-    pub me: usize,
     pub data: Option<Data>,
-    pub map: GameMap,
-    pub enemies: Enemies,
-    pub towers: Towers,
+    pub sim: Simulation,
     pub background: Background,
-    pub waves: Waves,
     pub gui: Gui,
-    pub players: HashMap<usize, Player>,
-    pub projectiles: Projectiles,
-    pub effects: Effects,
     pub overlay_state: Option<Box<OverlayState>>,
-    time: f32,
+    pub replay: ReplayMode,
+    pub clock: GameClock,
+    pub effects: Effects,
+    pub statistics: Statistics,
+    pub interpolation: f32,
+    // for the background and views like the pile overlays, sim.rng is only used by the
+    // simulation so the same seed plays the same with and without a window
+    pub cosmetic_rng: StdRng,
 }
 
 impl PlayingState {
-    pub fn new(debug: bool) -> Self {
+    pub fn new(level: &str, debug: bool, seed: u64) -> Self {
         let data = None;
-        let map = GameMap::new();
-        let enemies = Enemies::new();
-        let towers = Towers::new();
-        let waves = Waves::new();
+        let sim = Simulation::new(level, debug, seed);
         let gui = Gui::new();
-        let projectiles = Projectiles::new();
-        let mut players = HashMap::new();
-        let me = 42;
-        let mut player = Player::new(me);
-        if debug {
-            player = Player::debug(me);
-        }
-        let effects = Effects::new();
-        let background = Background::new();
-        players.insert(me, player);
+        let mut cosmetic_rng = StdRng::seed_from_u64(seed.wrapping_add(1));
+        let background = Background::new(&mut cosmetic_rng);
 
         return Self {
-            me,
             data,
-            map,
-            enemies,
-            towers,
-            waves,
+            sim,
             gui,
-            projectiles,
             overlay_state: None,
-            players,
-            effects,
             background,
-            time: 0.0,
+            replay: ReplayMode::Off,
+            clock: GameClock::new(),
+            effects: Effects::new(),
+            statistics: Statistics::new(),
+            interpolation: 1.0,
+            cosmetic_rng,
         };
     }
 
+    pub fn recording(level: &str, debug: bool, seed: u64, path: String) -> Self {
+        let mut res = PlayingState::new(level, debug, seed);
+        let file = Replay::new(level, seed, debug).create(&path);
+        res.replay = ReplayMode::Record { file };
+        return res;
+    }
+
+    pub fn replaying(replay: Replay) -> Self {
+        let mut res = PlayingState::new(&replay.level, replay.debug, replay.seed);
+        res.replay = ReplayMode::Playback { replay, next: 0 };
+        return res;
+    }
+
+    pub fn resume(save: SaveGame) -> Result<Self, String> {
+        let mut res = PlayingState::new(&save.level, save.debug, save.seed);
+        res.statistics = save.statistics.clone();
+        res.sim = save.into_sim()?;
+        return Ok(res);
+    }
+
+    // Validates and runs a command, returns whether it had any effect
+    pub fn execute(&mut self, command: Command) -> bool {
+        if !command.is_valid(self) {
+            return false;
+        }
+        let tick = self.sim.time() as usize;
+        self.replay.record(tick, command.clone());
+        command.apply(self);
+        self.dispatch_events();
+        return true;
+    }
+
+    fn dispatch_events(&mut self) {
+        for event in self.sim.events.drain() {
+            self.effects.on_event(&event);
+            self.statistics.on_event(&event);
+        }
+    }
+
+    fn play_back(&mut self) {
+        let tick = self.sim.time() as usize;
+        for command in self.replay.due_commands(tick) {
+            self.execute(command);
+        }
+        // overlays are only views, everything they change is part of the replay
+        self.overlay_state = None;
+    }
+
+    fn step(&mut self) {
+        if self.replay.is_playback() {
+            self.play_back();
+        }
+        self.sim.tick();
+        self.dispatch_events();
+    }
+
+    // Plays the game without a window, like Simulation::run but with the replay's commands
+    pub fn run(&mut self, max_ticks: usize) -> Option<Outcome> {
+        for _ in 0..max_ticks {
+            if let Some(outcome) = self.sim.outcome() {
+                return Some(outcome);
+            }
+            self.step();
+        }
+        return self.sim.outcome();
+    }
+
     pub fn time(&self) -> f32 {
-        return self.time;
+        return self.sim.time();
     }
 
     pub fn player_mut(&mut self) -> &mut Player {
-        self.players.get_mut(&self.me).unwrap()
+        self.sim.player_mut()
     }
 
     pub fn player(&self) -> &Player {
-        self.players.get(&self.me).unwrap()
+        self.sim.player()
     }
 }
 
 impl event_handler::GameState for PlayingState {
-    fn update(&mut self, _ctx: &mut Context) -> GameResult<event_handler::StateTransition> {
-        self.time += 1.0;
+    fn update(&mut self, ctx: &mut Context) -> GameResult<event_handler::StateTransition> {
+        let steps = self.clock.due_steps(ctx);
         if let Some(mut overlay) = self.overlay_state.take() {
             match overlay.update(self)? {
                 StateTransition::Stay => self.overlay_state = Some(overlay),
@@ -92,37 +154,36 @@ impl event_handler::GameState for PlayingState {
             }
             return Ok(event_handler::StateTransition::Stay);
         }
-        const _DESIRED_FPS: u32 = 60;
-        if self.player().hp <= 0 {
-            return Ok(event_handler::StateTransition::Next(Box::new(
-                EndState::failed(),
-            )));
-        }
-        if self.waves.status == WaveStatus::LevelFinished {
-            return Ok(event_handler::StateTransition::Next(Box::new(
-                EndState::win(),
-            )));
+        for _ in 0..steps {
+            match self.sim.outcome() {
+                Some(Outcome::Lost) => {
+                    let mut summary = self.statistics.info();
+                    if self.sim.settings.endless.is_some() {
+                        summary =
+                            format!("Survived {} waves\n{}", self.sim.waves.cleared(), summary);
+                    }
+                    return Ok(event_handler::StateTransition::Next(Box::new(
+                        EndState::failed(summary),
+                    )));
+                }
+                Some(Outcome::Won) => {
+                    return Ok(event_handler::StateTransition::Next(Box::new(
+                        EndState::win(self.statistics.info()),
+                    )));
+                }
+                None => {}
+            }
+            self.step();
         }
-        Player::tick(self);
+        // animations run at the frame rate, independent of pause and game speed
+        self.effects.tick();
         Gui::tick(self);
-        Waves::tick(self);
-        Enemies::tick(self);
-        Towers::tick(self);
-        Projectiles::tick(self);
         self.background.tick();
-        self.effects.tick();
-        if self.waves.status == WaveStatus::WaveFinished {
-            self.waves.status = WaveStatus::Waiting(5 * 60);
-        }
-        if self.waves.status == WaveStatus::Ready {
-            //self.player_mut().deck.discard_all();
-            //self.player_mut().deck.draw(5);
-            self.waves.status = WaveStatus::Ongoing;
-        }
         return Ok(event_handler::StateTransition::Stay);
     }
 
     fn draw(&mut self, ctx: &mut Context) -> GameResult<()> {
+        self.interpolation = self.clock.interpolation(ctx);
         if let Some(overlay) = self.overlay_state.take() {
             overlay.draw(self, ctx)?;
             self.overlay_state = Some(overlay);
@@ -149,6 +210,25 @@ impl event_handler::GameState for PlayingState {
         keymod: KeyMods,
         repeat: bool,
     ) -> event_handler::StateTransition {
+        // the clock can be controlled while watching a replay as well
+        match keycode {
+            KeyCode::P => {
+                self.clock.toggle_pause();
+                return event_handler::StateTransition::Stay;
+            }
+            KeyCode::F => {
+                self.clock.cycle_speed();
+                return event_handler::StateTransition::Stay;
+            }
+            _ => {}
+        }
+        if self.replay.is_playback() {
+            return event_handler::StateTransition::Stay;
+        }
+        if keycode == KeyCode::F5 {
+            SaveGame::from_sim(&self.sim, &self.statistics).save();
+            return event_handler::StateTransition::Next(Box::new(MenuState::new(None, None)));
+        }
         if let Some(mut overlay) = self.overlay_state.take() {
             match overlay.key_down_event(self, keycode, keymod, repeat) {
                 StateTransition::Stay => self.overlay_state = Some(overlay),
@@ -161,6 +241,15 @@ impl event_handler::GameState for PlayingState {
         return event_handler::StateTransition::Stay;
     }
 
+    fn mouse_motion_event(&mut self, _ctx: &mut Context, x: f32, y: f32) {
+        // hovering the wave preview works while watching a replay as well
+        self.gui.mouse = Point::new(x, y);
+        if self.replay.is_playback() || self.overlay_state.is_some() {
+            return;
+        }
+        Gui::mouse_moved(self, Point::new(x, y));
+    }
+
     fn set_data(&mut self, data: Data) {
         self.data = Some(data);
     }
@@ -168,3 +257,18 @@ impl event_handler::GameState for PlayingState {
         return self.data.take().unwrap();
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn replay_plays_back_recorded_commands() {
+        // builds an Archer right away, so the game lasts longer than without any commands
+        let replay = Replay::load("resources/replays/tutorial.ron");
+        let mut state = PlayingState::replaying(replay);
+        assert_eq!(state.run(60 * 60 * 60), Some(Outcome::Lost));
+        assert_eq!(state.time() as usize, 3166);
+        assert_eq!(state.sim.towers.built.len(), 1);
+    }
+}
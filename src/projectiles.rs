@@ -1,32 +1,77 @@
 use crate::algebra::{Point, Vector};
 use crate::assets::{Data, ImgID};
-use crate::buffs::BuffType;
-use crate::debuffs::Debuff;
-use crate::effects::Effects;
+use crate::debuffs::{Debuff, DebuffType};
 use crate::enemies::Enemies;
+use crate::game_events::{GameEvent, GameEvents};
 use crate::playing_state::PlayingState;
-use crate::utils::move_to;
+use crate::simulation::Simulation;
+use crate::utils::{distance, lerp, move_to};
 use ggez::graphics;
 use ggez::{Context, GameResult};
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
+
+// How close an enemy has to be to a piercing projectile to get hit
+pub const PIERCE_RADIUS: f32 = 20.0;
+
+// What happens when a projectile reaches its target
+#[derive(PartialEq, Clone, Debug, Serialize, Deserialize)]
+pub enum ProjectileBehavior {
+    // only the target is hit
+    Single,
+    // every enemy within radius of the impact is hit
+    Splash {
+        radius: f32,
+    },
+    // flies straight through the enemies in its way until it hit that many or left the tower's range
+    Pierce {
+        hits: usize,
+    },
+    // after the target, jumps to the closest enemy not hit yet within range, up to jumps times,
+    // every jump deals falloff percent less damage than the one before
+    Chain {
+        jumps: usize,
+        range: f32,
+        #[serde(default)]
+        falloff: usize,
+    },
+    // hits the target the moment it is fired
+    Beam,
+}
+
+impl Default for ProjectileBehavior {
+    fn default() -> Self {
+        return ProjectileBehavior::Single;
+    }
+}
 
 #[derive(Debug)]
 pub struct Projectile {
-    disp: ImgID,
-    enemy_id: usize,
-    tower_id: usize,
-    position: Point,
-    damage: usize,
-    speed: f32,
-    next_walk_target: Point,
-    reached_goal: bool,
-    debuffs: HashMap<BuffType, Debuff>,
+    pub disp: ImgID,
+    pub enemy_id: usize,
+    pub tower_id: usize,
+    pub owner: usize,
+    pub position: Point,
+    pub last_position: Point,
+    pub damage: usize,
+    pub speed: f32,
+    pub next_walk_target: Point,
+    pub reached_goal: bool,
+    pub behavior: ProjectileBehavior,
+    pub debuffs: HashMap<DebuffType, Debuff>,
+    // where it was fired from, piercing projectiles vanish range away from it
+    pub origin: Point,
+    pub range: f32,
+    // the straight line piercing projectiles fly along, fixed on their first tick
+    pub direction: Option<Vector>,
+    // enemies a piercing projectile already went through
+    pub pierced: Vec<usize>,
 }
 
 impl Projectile {
     pub fn new(
         position: Point,
         tower_id: usize,
+        owner: usize,
         enemy_id: usize,
         damage: usize,
         speed: f32,
@@ -35,30 +80,149 @@ impl Projectile {
             disp: ImgID::Arrow,
             debuffs: HashMap::new(),
             tower_id,
+            owner,
             enemy_id,
             position,
+            last_position: position,
             damage,
             next_walk_target: position,
             speed,
             reached_goal: false,
+            behavior: ProjectileBehavior::Single,
+            origin: position,
+            range: 0.0,
+            direction: None,
+            pierced: vec![],
         };
     }
 
-    pub fn tick(&mut self, enemies: &mut Enemies, effects: &mut Effects) {
-        if let Some(e) = enemies.enemies.get(&self.enemy_id) {
-            self.next_walk_target = e.position;
+    pub fn tick(&mut self, enemies: &mut Enemies, events: &mut GameEvents) {
+        self.last_position = self.position;
+        match self.behavior {
+            ProjectileBehavior::Pierce { hits } => self.pierce(hits, enemies, events),
+            ProjectileBehavior::Beam => {
+                if let Some(e) = enemies.enemies.get(&self.enemy_id) {
+                    events.publish(GameEvent::BeamFired {
+                        from: self.position,
+                        to: e.position,
+                        img: self.disp,
+                    });
+                    self.hit(self.enemy_id, self.damage, enemies, events);
+                }
+                self.reached_goal = true;
+            }
+            _ => {
+                if let Some(e) = enemies.enemies.get(&self.enemy_id) {
+                    self.next_walk_target = e.position;
+                }
+                let (new_pos, finished) = move_to(self.position, self.next_walk_target, self.speed);
+                self.position = new_pos;
+                self.reached_goal = finished;
+                if self.reached_goal == true {
+                    for (enemy, damage) in self.hit_enemies(enemies) {
+                        self.hit(enemy, damage, enemies, events);
+                    }
+                }
+            }
         }
-        let (new_pos, finished) = move_to(self.position, self.next_walk_target, self.speed);
-        self.position = new_pos;
-        self.reached_goal = finished;
-        if self.reached_goal == true {
-            enemies.damage(self.enemy_id, self.damage);
-            enemies.debuff(self.enemy_id, &self.debuffs);
-            effects.smoke(self.next_walk_target.x, self.next_walk_target.y);
+    }
+
+    fn pierce(&mut self, hits: usize, enemies: &mut Enemies, events: &mut GameEvents) {
+        if self.direction.is_none() {
+            match enemies.enemies.get(&self.enemy_id) {
+                Some(e) if e.position != self.position => {
+                    self.direction = Some((e.position - self.position).normalize());
+                }
+                _ => {
+                    self.reached_goal = true;
+                    return;
+                }
+            }
+        }
+        let direction = self.direction.unwrap();
+        self.position += direction * self.speed;
+        self.next_walk_target = self.position + direction;
+        for enemy in enemies.in_range(self.position, PIERCE_RADIUS) {
+            if self.pierced.len() >= hits {
+                break;
+            }
+            if !self.pierced.contains(&enemy) {
+                self.pierced.push(enemy);
+                self.hit(enemy, self.damage, enemies, events);
+            }
+        }
+        self.reached_goal =
+            self.pierced.len() >= hits || distance(&self.origin, &self.position) >= self.range;
+    }
+
+    fn hit(&self, enemy: usize, damage: usize, enemies: &mut Enemies, events: &mut GameEvents) {
+        let position = enemies.enemies[&enemy].position;
+        let killed = enemies.damage(enemy, damage, self.owner);
+        enemies.debuff(enemy, &self.debuffs);
+        events.publish(GameEvent::EnemyHit { enemy, position });
+        if killed {
+            events.publish(GameEvent::EnemyKilled {
+                enemy,
+                by_tower: Some(self.tower_id),
+                by_player: self.owner,
+                position,
+            });
         }
     }
 
-    pub fn add_debuff(&mut self, debuff: Debuff) {
+    // The enemies hit on impact and the damage each of them takes
+    fn hit_enemies(&self, enemies: &Enemies) -> Vec<(usize, usize)> {
+        match self.behavior {
+            ProjectileBehavior::Splash { radius } => {
+                return enemies
+                    .in_range(self.next_walk_target, radius)
+                    .into_iter()
+                    .map(|e| (e, self.damage))
+                    .collect();
+            }
+            ProjectileBehavior::Chain {
+                jumps,
+                range,
+                falloff,
+            } => {
+                if !enemies.enemies.contains_key(&self.enemy_id) {
+                    return vec![];
+                }
+                let mut hit = vec![(self.enemy_id, self.damage)];
+                let mut last = enemies.enemies[&self.enemy_id].position;
+                let mut damage = self.damage;
+                for _ in 0..jumps {
+                    damage = damage * (100 - falloff.min(100)) / 100;
+                    let next = enemies
+                        .in_range(last, range)
+                        .into_iter()
+                        .filter(|e| !hit.iter().any(|(h, _)| h == e))
+                        .min_by(|a, b| {
+                            let da = distance(&last, &enemies.enemies[a].position);
+                            let db = distance(&last, &enemies.enemies[b].position);
+                            return da.partial_cmp(&db).unwrap();
+                        });
+                    match next {
+                        Some(e) => {
+                            last = enemies.enemies[&e].position;
+                            hit.push((e, damage));
+                        }
+                        None => break,
+                    }
+                }
+                return hit;
+            }
+            _ => {
+                if !enemies.enemies.contains_key(&self.enemy_id) {
+                    return vec![];
+                }
+                return vec![(self.enemy_id, self.damage)];
+            }
+        }
+    }
+
+    pub fn add_debuff(&mut self, mut debuff: Debuff) {
+        debuff.owner = self.owner;
         match self.debuffs.get(&debuff.kind) {
             Some(own) => {
                 if own.effectiveness < debuff.effectiveness {
@@ -74,14 +238,14 @@ impl Projectile {
 
 #[derive(Debug)]
 pub struct Projectiles {
-    pub projectiles: HashMap<usize, Projectile>,
-    id: usize,
+    pub projectiles: BTreeMap<usize, Projectile>,
+    pub id: usize,
 }
 
 impl Projectiles {
     pub fn new() -> Self {
         let id = 0;
-        let projectiles = HashMap::new();
+        let projectiles = BTreeMap::new();
         return Self { projectiles, id };
     }
 
@@ -91,14 +255,18 @@ impl Projectiles {
     }
 
     pub fn draw(state: &PlayingState, data: &Data, ctx: &mut Context) -> GameResult<()> {
-        for p in state.projectiles.projectiles.values() {
+        for p in state.sim.projectiles.projectiles.values() {
             let dir = p.next_walk_target - p.position;
             let rot = dir.y.atan2(dir.x);
             graphics::draw(
                 ctx,
                 data.get_i(&p.disp),
                 graphics::DrawParam::default()
-                    .dest(state.gui.cam().world_pos(p.position))
+                    .dest(state.gui.cam().world_pos(lerp(
+                        p.last_position,
+                        p.position,
+                        state.interpolation,
+                    )))
                     .rotation(rot)
                     .offset(Point::new(0.5, 0.5))
                     .scale(Vector::new(4.0, 4.0)),
@@ -107,13 +275,127 @@ impl Projectiles {
         Ok(())
     }
 
-    pub fn tick(state: &mut PlayingState) {
-        for p in state.projectiles.projectiles.values_mut() {
-            p.tick(&mut state.enemies, &mut state.effects)
+    pub fn tick(sim: &mut Simulation) {
+        for p in sim.projectiles.projectiles.values_mut() {
+            p.tick(&mut sim.enemies, &mut sim.events)
         }
-        state
-            .projectiles
+        sim.projectiles
             .projectiles
             .retain(|_id, p| p.reached_goal == false);
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::enemy::Enemy;
+
+    // Brutes, so nothing dies and every hit shows in their health
+    fn enemies(positions: &[(f32, f32)]) -> Enemies {
+        let mut enemies = Enemies::new();
+        for (x, y) in positions.iter() {
+            let brute = Enemy::at(Point::new(*x, *y), (0, 0), enemies.archetype("Brute"));
+            enemies.spawn(brute);
+        }
+        return enemies;
+    }
+
+    fn projectile(target: usize, behavior: ProjectileBehavior) -> Projectile {
+        let mut p = Projectile::new(Point::new(0.0, 0.0), 0, 0, target, 40, 10.0);
+        p.behavior = behavior;
+        p.range = 200.0;
+        return p;
+    }
+
+    // Ticks the projectile until it is gone and returns the damage every enemy took
+    fn fire(mut p: Projectile, enemies: &mut Enemies) -> Vec<usize> {
+        let mut events = GameEvents::new();
+        for _ in 0..100 {
+            if p.reached_goal {
+                break;
+            }
+            p.tick(enemies, &mut events);
+        }
+        assert!(p.reached_goal);
+        return enemies
+            .enemies
+            .values()
+            .map(|e| e.stats.health - e.health)
+            .collect();
+    }
+
+    #[test]
+    fn single_hits_only_the_target() {
+        let mut enemies = enemies(&[(50.0, 0.0), (55.0, 0.0)]);
+        let p = projectile(0, ProjectileBehavior::Single);
+        assert_eq!(fire(p, &mut enemies), vec![40, 0]);
+    }
+
+    #[test]
+    fn splash_hits_everything_around_the_impact() {
+        let mut enemies = enemies(&[(50.0, 0.0), (50.0, 25.0), (90.0, 0.0), (20.0, 0.0)]);
+        let p = projectile(0, ProjectileBehavior::Splash { radius: 30.0 });
+        assert_eq!(fire(p, &mut enemies), vec![40, 40, 0, 40]);
+    }
+
+    #[test]
+    fn pierce_goes_through_the_line_it_was_fired_along() {
+        let positions = [(50.0, 0.0), (100.0, 0.0), (150.0, 0.0), (100.0, 80.0)];
+        let mut line = enemies(&positions);
+        let p = projectile(0, ProjectileBehavior::Pierce { hits: 2 });
+        assert_eq!(fire(p, &mut line), vec![40, 40, 0, 0]);
+        // or until it left the tower's range
+        let mut line = enemies(&positions);
+        let mut p = projectile(0, ProjectileBehavior::Pierce { hits: 5 });
+        p.range = 120.0;
+        assert_eq!(fire(p, &mut line), vec![40, 40, 0, 0]);
+    }
+
+    #[test]
+    fn chain_jumps_to_the_closest_enemy_not_hit_yet() {
+        let positions = [(100.0, 0.0), (160.0, 0.0), (130.0, 0.0), (300.0, 0.0)];
+        let mut enemies = enemies(&positions);
+        let chain = ProjectileBehavior::Chain {
+            jumps: 3,
+            range: 50.0,
+            falloff: 50,
+        };
+        // 100 -> 130 -> 160, the last one is out of range
+        assert_eq!(
+            fire(projectile(0, chain), &mut enemies),
+            vec![40, 10, 20, 0]
+        );
+    }
+
+    #[test]
+    fn chain_stops_after_its_jumps() {
+        let positions = [(100.0, 0.0), (130.0, 0.0), (160.0, 0.0)];
+        let mut enemies = enemies(&positions);
+        let chain = ProjectileBehavior::Chain {
+            jumps: 1,
+            range: 50.0,
+            falloff: 0,
+        };
+        assert_eq!(fire(projectile(0, chain), &mut enemies), vec![40, 40, 0]);
+    }
+
+    #[test]
+    fn beams_hit_right_away() {
+        let mut enemies = enemies(&[(500.0, 0.0), (505.0, 0.0)]);
+        let mut events = GameEvents::new();
+        let mut p = projectile(0, ProjectileBehavior::Beam);
+        p.tick(&mut enemies, &mut events);
+        assert!(p.reached_goal);
+        assert_eq!(enemies.enemies[&0].health, 60);
+        assert_eq!(enemies.enemies[&1].health, 100);
+        let beams = events
+            .drain()
+            .into_iter()
+            .filter(|e| match e {
+                GameEvent::BeamFired { .. } => true,
+                _ => false,
+            })
+            .count();
+        assert_eq!(beams, 1);
+    }
+}
@@ -0,0 +1,110 @@
+use crate::command::Command;
+use ron::de::from_str;
+use ron::ser::to_string;
+use std::fs::File;
+use std::io::{BufRead, BufReader, Write};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Replay {
+    pub level: String,
+    pub seed: u64,
+    pub debug: bool,
+    pub commands: Vec<(usize, Command)>,
+}
+
+impl Replay {
+    pub fn new(level: &str, seed: u64, debug: bool) -> Self {
+        return Self {
+            level: level.to_string(),
+            seed,
+            debug,
+            commands: vec![],
+        };
+    }
+
+    // The first line holds level, seed and debug flag, every following line one (tick, command)
+    pub fn load(path: &str) -> Self {
+        let f = File::open(path).expect(&format!("Failed opening replay {}", path));
+        let mut lines = BufReader::new(f).lines();
+        let header = lines.next().and_then(|l| l.ok()).unwrap_or_default();
+        let mut replay: Replay = match from_str(&header) {
+            Ok(x) => x,
+            Err(e) => {
+                println!("Failed to load replay {}: {}", path, e);
+                ::std::process::exit(1);
+            }
+        };
+        for (i, line) in lines.enumerate() {
+            let line = line.expect(&format!("Failed reading replay {}", path));
+            match from_str(&line) {
+                Ok(command) => replay.commands.push(command),
+                Err(e) => {
+                    println!("Failed to load replay {} line {}: {}", path, i + 2, e);
+                    ::std::process::exit(1);
+                }
+            }
+        }
+        return replay;
+    }
+
+    // Writes the header, commands are appended one line at a time while recording
+    pub fn create(&self, path: &str) -> File {
+        let mut f = File::create(path).expect(&format!("Failed creating replay {}", path));
+        let header = Replay {
+            commands: vec![],
+            ..self.clone()
+        };
+        writeln!(
+            f,
+            "{}",
+            to_string(&header).expect("Failed serializing replay")
+        )
+        .expect(&format!("Failed writing replay {}", path));
+        for command in self.commands.iter() {
+            Replay::append(&mut f, command);
+        }
+        return f;
+    }
+
+    fn append(f: &mut File, command: &(usize, Command)) {
+        let line = to_string(command).expect("Failed serializing replay command");
+        writeln!(f, "{}", line).expect("Failed writing replay");
+    }
+}
+
+// Playing states either record what the player does or play back a recorded replay
+pub enum ReplayMode {
+    Off,
+    Record { file: File },
+    Playback { replay: Replay, next: usize },
+}
+
+impl ReplayMode {
+    pub fn record(&mut self, tick: usize, command: Command) {
+        if let ReplayMode::Record { file } = self {
+            Replay::append(file, &(tick, command));
+        }
+    }
+
+    pub fn is_playback(&self) -> bool {
+        if let ReplayMode::Playback { .. } = self {
+            return true;
+        }
+        return false;
+    }
+
+    // Returns all recorded commands that happened before the given tick was simulated
+    pub fn due_commands(&mut self, tick: usize) -> Vec<Command> {
+        let mut due = vec![];
+        if let ReplayMode::Playback { replay, next } = self {
+            while let Some((command_tick, command)) = replay.commands.get(*next) {
+                if *command_tick > tick {
+                    break;
+                }
+                due.push(command.clone());
+                *next += 1;
+            }
+        }
+        return due;
+    }
+}
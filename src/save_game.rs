@@ -0,0 +1,468 @@
+use crate::algebra::{Point, Vector};
+use crate::assets::ImgID;
+use crate::buffs::{Buff, BuffType};
+use crate::card::CardType;
+use crate::card_deck::CardDeck;
+use crate::debuffs::{Debuff, DebuffType};
+use crate::enemy::Enemy;
+use crate::level::available_levels;
+use crate::player::Player;
+use crate::projectiles::{Projectile, ProjectileBehavior};
+use crate::sim_rng::SimRng;
+use crate::simulation::Simulation;
+use crate::statistics::Statistics;
+use crate::tower::{TargetingMode, Tower};
+use crate::towers::Towers;
+use crate::wave::{ActiveWave, WaveSpec, WaveStatus};
+use ron::de::from_reader;
+use ron::ser::{to_string_pretty, PrettyConfig};
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::Write;
+use std::path::Path;
+
+const SAVE_PATH: &str = "saves/savegame.ron";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WavesSave {
+    pub id: usize,
+    pub status: WaveStatus,
+    pub active: Vec<ActiveWave>,
+    // waves made up by endless mode so far
+    pub generated: Vec<WaveSpec>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EnemySave {
+    pub kind: String,
+    // the archetype's values can be scaled up in endless mode
+    pub max_health: usize,
+    pub speed: f32,
+    pub position: (f32, f32),
+    pub health: usize,
+    pub shield: usize,
+    pub next_walk_target: (f32, f32),
+    pub tile: (usize, usize),
+    pub age: usize,
+    pub haste: usize,
+    pub killed_by: Option<usize>,
+    pub debuffs: HashMap<DebuffType, Vec<Debuff>>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TowerSave {
+    pub id: usize,
+    pub kind: String,
+    pub owner: usize,
+    pub targeting: TargetingMode,
+    pub invested: usize,
+    pub upgrades: Vec<usize>,
+    pub cooldown: usize,
+    pub map_position: (usize, usize),
+    pub buffs: HashMap<BuffType, usize>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ProjectileSave {
+    pub disp: ImgID,
+    pub enemy_id: usize,
+    pub tower_id: usize,
+    pub owner: usize,
+    pub position: (f32, f32),
+    pub damage: usize,
+    pub speed: f32,
+    pub next_walk_target: (f32, f32),
+    pub behavior: ProjectileBehavior,
+    pub debuffs: HashMap<DebuffType, Debuff>,
+    pub origin: (f32, f32),
+    pub range: f32,
+    pub direction: Option<(f32, f32)>,
+    pub pierced: Vec<usize>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PlayerSave {
+    pub id: usize,
+    pub hp: usize,
+    pub gold: usize,
+    pub mana: f32,
+    pub hand: Vec<CardType>,
+    pub actions: Vec<CardType>,
+    pub deck: Vec<CardType>,
+    pub discard: Vec<CardType>,
+}
+
+// A snapshot of a running Simulation. The map and all specs are reloaded from
+// their ron files, so only the parts that change during a game are stored here.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SaveGame {
+    pub level: String,
+    pub seed: u64,
+    pub debug: bool,
+    // random numbers the simulation used up so far
+    pub rng_words: u64,
+    pub statistics: Statistics,
+    pub time: f32,
+    pub me: usize,
+    pub waves: WavesSave,
+    pub enemies: HashMap<usize, EnemySave>,
+    pub next_enemy_id: usize,
+    pub towers: Vec<TowerSave>,
+    pub projectiles: HashMap<usize, ProjectileSave>,
+    pub next_projectile_id: usize,
+    pub players: Vec<PlayerSave>,
+}
+
+fn from_point(p: Point) -> (f32, f32) {
+    return (p.x, p.y);
+}
+
+fn to_point(p: (f32, f32)) -> Point {
+    return Point::new(p.0, p.1);
+}
+
+impl SaveGame {
+    pub fn exists() -> bool {
+        return Path::new(SAVE_PATH).exists();
+    }
+
+    // A save game can only be continued once, it is removed when loaded. One that
+    // can't be read (e.g. from an older version) is removed as well.
+    pub fn load() -> Result<Self, String> {
+        let f = File::open(SAVE_PATH).map_err(|e| format!("Failed opening save game: {}", e))?;
+        let res = from_reader(f).map_err(|e| format!("Failed to load save game: {}", e));
+        SaveGame::delete();
+        return res;
+    }
+
+    fn delete() {
+        if SaveGame::exists() {
+            fs::remove_file(SAVE_PATH).expect("Failed removing save game");
+        }
+    }
+
+    pub fn save(&self) {
+        if let Some(dir) = Path::new(SAVE_PATH).parent() {
+            fs::create_dir_all(dir).expect("Failed creating save game directory");
+        }
+        let serialized =
+            to_string_pretty(self, PrettyConfig::default()).expect("Failed serializing save game");
+        let mut f = File::create(SAVE_PATH).expect("Failed creating save game");
+        f.write_all(serialized.as_bytes())
+            .expect("Failed writing save game");
+    }
+
+    pub fn from_sim(sim: &Simulation, statistics: &Statistics) -> Self {
+        let waves = WavesSave {
+            id: sim.waves.id,
+            status: sim.waves.status,
+            active: sim.waves.active.clone(),
+            generated: sim.waves.waves[sim.waves.scripted..].to_vec(),
+        };
+        let enemies = sim
+            .enemies
+            .enemies
+            .iter()
+            .map(|(id, e)| {
+                let save = EnemySave {
+                    kind: e.stats.name.clone(),
+                    max_health: e.stats.health,
+                    speed: e.stats.speed,
+                    position: from_point(e.position),
+                    health: e.health,
+                    shield: e.shield,
+                    next_walk_target: from_point(e.next_walk_target),
+                    tile: e.tile,
+                    age: e.age,
+                    haste: e.haste,
+                    killed_by: e.killed_by,
+                    debuffs: e.debuffs.clone(),
+                };
+                (*id, save)
+            })
+            .collect();
+        let towers = sim
+            .towers
+            .built
+            .values()
+            .map(|t| TowerSave {
+                id: t.id,
+                kind: t.kind.clone(),
+                owner: t.owner,
+                targeting: t.targeting,
+                invested: t.invested,
+                upgrades: t.upgrades.clone(),
+                cooldown: t.cooldown,
+                map_position: t.map_position,
+                buffs: t.buffs.iter().map(|(kind, b)| (*kind, b.level)).collect(),
+            })
+            .collect();
+        let projectiles = sim
+            .projectiles
+            .projectiles
+            .iter()
+            .map(|(id, p)| {
+                let save = ProjectileSave {
+                    disp: p.disp,
+                    enemy_id: p.enemy_id,
+                    tower_id: p.tower_id,
+                    owner: p.owner,
+                    position: from_point(p.position),
+                    damage: p.damage,
+                    speed: p.speed,
+                    next_walk_target: from_point(p.next_walk_target),
+                    behavior: p.behavior.clone(),
+                    debuffs: p.debuffs.clone(),
+                    origin: from_point(p.origin),
+                    range: p.range,
+                    direction: p.direction.map(|d| (d.x, d.y)),
+                    pierced: p.pierced.clone(),
+                };
+                (*id, save)
+            })
+            .collect();
+        let players = sim
+            .players
+            .values()
+            .map(|p| PlayerSave {
+                id: p.id,
+                hp: p.hp,
+                gold: p.gold,
+                mana: p.mana,
+                hand: p.deck.hand.clone(),
+                actions: p.deck.actions.clone(),
+                deck: p.deck.deck.clone(),
+                discard: p.deck.discard.clone(),
+            })
+            .collect();
+        return Self {
+            level: sim.level.clone(),
+            seed: sim.seed,
+            debug: sim.debug,
+            rng_words: sim.rng.words,
+            statistics: statistics.clone(),
+            time: sim.time(),
+            me: sim.me,
+            waves,
+            enemies,
+            next_enemy_id: sim.enemies.id,
+            towers,
+            projectiles,
+            next_projectile_id: sim.projectiles.id,
+            players,
+        };
+    }
+
+    fn restore_tower(save: &TowerSave, towers: &Towers) -> Result<Tower, String> {
+        let stats = towers
+            .kind(&save.kind)
+            .ok_or_else(|| format!("Unknown tower kind {}", save.kind))?;
+        let mut tower = Tower::new(stats, save.map_position, save.owner);
+        tower.targeting = save.targeting;
+        tower.invested = save.invested;
+        // tower.ron may have changed since the game was saved
+        for branch in save.upgrades.iter() {
+            if !tower.upgrade(*branch) {
+                return Err(format!(
+                    "Unknown upgrade branch {} for {}",
+                    branch, save.kind
+                ));
+            }
+        }
+        tower.id = save.id;
+        tower.cooldown = save.cooldown;
+        for (kind, level) in save.buffs.iter() {
+            let stats = towers
+                .buff_stats
+                .get(kind)
+                .ok_or_else(|| format!("Unknown buff {:?}", kind))?
+                .clone();
+            let mut buff = Buff::new(stats);
+            buff.level = *level;
+            tower.buffs.insert(*kind, buff);
+        }
+        return Ok(tower);
+    }
+
+    // The ron files may have changed since the game was saved, everything the save
+    // refers to by name has to still be there
+    fn check_specs(&self, sim: &Simulation) -> Result<(), String> {
+        let generated = self.waves.generated.iter().flat_map(|w| w.groups.iter());
+        let enemies = self.enemies.values().map(|e| &e.kind);
+        for kind in enemies.chain(generated.map(|g| &g.enemy)) {
+            if !sim.enemies.archetypes.contains_key(kind) {
+                return Err(format!("Unknown enemy {}", kind));
+            }
+        }
+        let wave_count = sim.waves.waves.len() + self.waves.generated.len();
+        if self.waves.id > wave_count || self.waves.active.iter().any(|a| a.id >= wave_count) {
+            return Err(format!("The level {} has fewer waves", self.level));
+        }
+        for p in self.players.iter() {
+            let piles = p
+                .hand
+                .iter()
+                .chain(&p.actions)
+                .chain(&p.deck)
+                .chain(&p.discard);
+            for card in piles {
+                if !sim.cards.specs.contains_key(card) {
+                    return Err(format!("Unknown card {}", card.0));
+                }
+            }
+        }
+        return Ok(());
+    }
+
+    pub fn into_sim(self) -> Result<Simulation, String> {
+        if !available_levels().contains(&self.level) {
+            return Err(format!("Unknown level {}", self.level));
+        }
+        // a fresh simulation from the same seed recreates the same map decorations
+        let mut sim = Simulation::new(&self.level, self.debug, self.seed);
+        self.check_specs(&sim)?;
+        sim.rng = SimRng::restore(self.seed, self.rng_words);
+        sim.time = self.time;
+        sim.me = self.me;
+
+        sim.waves.id = self.waves.id;
+        sim.waves.status = self.waves.status;
+        sim.waves.active = self.waves.active;
+        sim.waves.waves.extend(self.waves.generated);
+
+        for (id, e) in self.enemies.into_iter() {
+            let enemy = Enemy {
+                stats: sim.enemies.archetype_with(&e.kind, e.max_health, e.speed),
+                position: to_point(e.position),
+                last_position: to_point(e.position),
+                health: e.health,
+                shield: e.shield,
+                next_walk_target: to_point(e.next_walk_target),
+                tile: e.tile,
+                reached_goal: false,
+                age: e.age,
+                haste: e.haste,
+                killed_by: e.killed_by,
+                debuffs: e.debuffs,
+            };
+            sim.enemies.enemies.insert(id, enemy);
+        }
+        sim.enemies.id = self.next_enemy_id;
+
+        for t in self.towers.iter() {
+            let tower = SaveGame::restore_tower(t, &sim.towers)?;
+            let (x, y) = tower.map_position;
+            sim.towers.restore(tower);
+            sim.map.place_tower(x, y);
+        }
+
+        for (id, p) in self.projectiles.into_iter() {
+            let projectile = Projectile {
+                disp: p.disp,
+                enemy_id: p.enemy_id,
+                tower_id: p.tower_id,
+                owner: p.owner,
+                position: to_point(p.position),
+                last_position: to_point(p.position),
+                damage: p.damage,
+                speed: p.speed,
+                next_walk_target: to_point(p.next_walk_target),
+                reached_goal: false,
+                behavior: p.behavior,
+                debuffs: p.debuffs,
+                origin: to_point(p.origin),
+                range: p.range,
+                direction: p.direction.map(|d| Vector::new(d.0, d.1)),
+                pierced: p.pierced,
+            };
+            sim.projectiles.projectiles.insert(id, projectile);
+        }
+        sim.projectiles.id = self.next_projectile_id;
+
+        sim.players.clear();
+        for p in self.players.into_iter() {
+            let deck = CardDeck {
+                hand: p.hand,
+                actions: p.actions,
+                deck: p.deck,
+                discard: p.discard,
+            };
+            let player = Player {
+                id: p.id,
+                hp: p.hp,
+                gold: p.gold,
+                mana: p.mana,
+                deck,
+            };
+            sim.players.insert(p.id, player);
+        }
+        return Ok(sim);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ron::de::from_str;
+
+    // Everything a player could tell apart, the save's maps are in hash order
+    fn snapshot(sim: &Simulation) -> Vec<String> {
+        let mut res = vec![format!(
+            "time {} rng {} wave {} {:?}",
+            sim.time(),
+            sim.rng.words,
+            sim.waves.id,
+            sim.waves.status
+        )];
+        for (id, e) in sim.enemies.enemies.iter() {
+            res.push(format!(
+                "enemy {} {} {} {} {:?} {:?}",
+                id, e.stats.name, e.health, e.shield, e.position, e.tile
+            ));
+        }
+        for p in sim.players.values() {
+            res.push(format!("player {} {} {} {}", p.id, p.hp, p.gold, p.mana));
+        }
+        res.sort();
+        return res;
+    }
+
+    fn saved_game() -> (Simulation, SaveGame) {
+        let mut sim = Simulation::new("endless", false, 7);
+        sim.run(900);
+        let save = SaveGame::from_sim(&sim, &Statistics::new());
+        let serialized = to_string_pretty(&save, PrettyConfig::default()).unwrap();
+        return (sim, from_str(&serialized).unwrap());
+    }
+
+    #[test]
+    fn restores_the_exact_game() {
+        let (mut sim, save) = saved_game();
+        assert!(!save.enemies.is_empty());
+        assert!(save.rng_words > 0);
+        let mut restored = save.into_sim().unwrap();
+        assert_eq!(snapshot(&restored), snapshot(&sim));
+        // both continue with the same random numbers
+        let outcome = sim.run(3000);
+        assert_eq!(restored.run(3000), outcome);
+        assert_eq!(snapshot(&restored), snapshot(&sim));
+    }
+
+    #[test]
+    fn rejects_saves_the_specs_no_longer_match() {
+        let (_, save) = saved_game();
+        let mut unknown_level = save.clone();
+        unknown_level.level = "removed".to_string();
+        assert!(unknown_level.into_sim().is_err());
+
+        let mut unknown_enemy = save.clone();
+        for e in unknown_enemy.enemies.values_mut() {
+            e.kind = "Removed".to_string();
+        }
+        assert!(unknown_enemy.into_sim().is_err());
+
+        let mut unknown_card = save.clone();
+        unknown_card.players[0].hand.push(CardType::new("Removed"));
+        assert!(unknown_card.into_sim().is_err());
+    }
+}
@@ -1,7 +1,7 @@
 use crate::algebra::{Point, Vector};
 use crate::assets::ImgID;
-use crate::buffs::BuffType;
 use crate::card::CardType;
+use crate::command::Command;
 use crate::event_handler::StateTransition;
 use crate::overlay_state::OverlayState;
 use crate::playing_state::PlayingState;
@@ -19,20 +19,8 @@ impl ShopOverlay {
         return Self { cur_selected: 0 };
     }
 
-    fn get_available_cards(&self, _state: &PlayingState) -> Vec<CardType> {
-        return vec![
-            CardType::DamageEnemy,
-            CardType::Coin(1),
-            CardType::Coin(2),
-            CardType::Coin(3),
-            CardType::Take2,
-            CardType::SellTower,
-            CardType::Buff(BuffType::Freeze),
-            CardType::Buff(BuffType::Damage),
-            CardType::Buff(BuffType::RPM),
-            CardType::Buff(BuffType::Range),
-            CardType::Buff(BuffType::Aura),
-        ];
+    pub fn get_available_cards(state: &PlayingState) -> Vec<CardType> {
+        return state.sim.cards.shop();
     }
 
     fn get_drawing_offset(&self) -> f32 {
@@ -43,7 +31,7 @@ impl ShopOverlay {
     }
 
     fn draw_available_cards(&self, state: &PlayingState, ctx: &mut Context) -> GameResult<()> {
-        for (i, card) in self.get_available_cards(state).iter().enumerate() {
+        for (i, card) in ShopOverlay::get_available_cards(state).iter().enumerate() {
             graphics::draw(
                 ctx,
                 state.data.as_ref().unwrap().get_i(&ImgID::Card),
@@ -57,7 +45,11 @@ impl ShopOverlay {
             )?;
             graphics::draw(
                 ctx,
-                state.data.as_ref().unwrap().get_i(&card.get_image_id()),
+                state
+                    .data
+                    .as_ref()
+                    .unwrap()
+                    .get_i(&card.get_image_id(state)),
                 graphics::DrawParam::default()
                     .dest(Point::new(
                         100.0,
@@ -101,16 +93,23 @@ impl ShopOverlay {
     }
 
     fn draw_selected(&self, state: &PlayingState, ctx: &mut Context) -> GameResult<()> {
-        let card = self.get_available_cards(state)[self.cur_selected];
+        let card = match ShopOverlay::get_available_cards(state).get(self.cur_selected) {
+            Some(card) => card.clone(),
+            None => return Ok(()),
+        };
         graphics::draw(
             ctx,
-            state.data.as_ref().unwrap().get_i(&card.get_image_id()),
+            state
+                .data
+                .as_ref()
+                .unwrap()
+                .get_i(&card.get_image_id(state)),
             graphics::DrawParam::default()
                 .dest(Point::new(300.0, 40.0))
                 .offset(Point::new(0.0, 0.0))
                 .scale(Vector::new(8.0, 8.0)),
         )?;
-        let desc = utils::text(state.data.as_ref().unwrap(), &card.get_description());
+        let desc = utils::text(state.data.as_ref().unwrap(), &card.get_description(state));
         graphics::draw(
             ctx,
             &desc,
@@ -145,23 +144,34 @@ impl OverlayState for ShopOverlay {
         _keymod: KeyMods,
         _repeat: bool,
     ) -> StateTransition {
+        // cards.ron doesn't have to put any card into the shop
+        if ShopOverlay::get_available_cards(state).is_empty() {
+            if keycode == KeyCode::Escape {
+                return StateTransition::Return;
+            }
+            return StateTransition::Stay;
+        }
         match keycode {
             KeyCode::Up => {
-                self.cur_selected =
-                    add_mod(self.cur_selected, -1, self.get_available_cards(state).len())
+                self.cur_selected = add_mod(
+                    self.cur_selected,
+                    -1,
+                    ShopOverlay::get_available_cards(state).len(),
+                )
             }
             KeyCode::Down => {
-                self.cur_selected =
-                    add_mod(self.cur_selected, 1, self.get_available_cards(state).len())
+                self.cur_selected = add_mod(
+                    self.cur_selected,
+                    1,
+                    ShopOverlay::get_available_cards(state).len(),
+                )
             }
             KeyCode::Escape => {
                 return StateTransition::Return;
             }
             KeyCode::Space => {
-                let card = self.get_available_cards(state)[self.cur_selected];
-                if state.player().gold >= card.aquisition_cost(state) {
-                    state.player_mut().gold -= card.aquisition_cost(state);
-                    state.player_mut().deck.buy_card(card);
+                let card = ShopOverlay::get_available_cards(state)[self.cur_selected].clone();
+                if state.execute(Command::BuyCard(card)) {
                     return StateTransition::Return;
                 }
                 return StateTransition::Stay;
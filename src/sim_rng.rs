@@ -0,0 +1,51 @@
+use rand::rngs::StdRng;
+use rand::{Error, RngCore, SeedableRng};
+
+// The simulation's random number generator. It counts the 32 bit words it used
+// up, so a save game can put it back exactly where it was.
+pub struct SimRng {
+    rng: StdRng,
+    pub words: u64,
+}
+
+impl SimRng {
+    pub fn new(seed: u64) -> Self {
+        return Self {
+            rng: StdRng::seed_from_u64(seed),
+            words: 0,
+        };
+    }
+
+    pub fn restore(seed: u64, words: u64) -> Self {
+        let mut res = SimRng::new(seed);
+        for _ in 0..words {
+            res.next_u32();
+        }
+        return res;
+    }
+}
+
+impl RngCore for SimRng {
+    fn next_u32(&mut self) -> u32 {
+        self.words += 1;
+        return self.rng.next_u32();
+    }
+
+    // StdRng always uses up two words for this, also across block boundaries
+    fn next_u64(&mut self) -> u64 {
+        self.words += 2;
+        return self.rng.next_u64();
+    }
+
+    fn fill_bytes(&mut self, dest: &mut [u8]) {
+        for chunk in dest.chunks_mut(4) {
+            let bytes = self.next_u32().to_le_bytes();
+            chunk.copy_from_slice(&bytes[..chunk.len()]);
+        }
+    }
+
+    fn try_fill_bytes(&mut self, dest: &mut [u8]) -> Result<(), Error> {
+        self.fill_bytes(dest);
+        return Ok(());
+    }
+}
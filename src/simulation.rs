@@ -0,0 +1,202 @@
+use crate::card::CardSpecs;
+use crate::enemies::Enemies;
+use crate::game_events::{GameEvent, GameEvents};
+use crate::level::{LevelSettings, PlayerSettings};
+use crate::map::GameMap;
+use crate::player::Player;
+use crate::projectiles::Projectiles;
+use crate::sim_rng::SimRng;
+use crate::towers::Towers;
+use crate::utils::load_level_specs;
+use crate::wave::{WaveStatus, Waves, WAVE_COUNTDOWN};
+use std::collections::HashMap;
+
+#[derive(Eq, PartialEq, Hash, Copy, Clone, Debug)]
+pub enum Outcome {
+    Won,
+    Lost,
+}
+
+// Everything that makes up a running game, without any window, input or assets.
+// Rendering and input handling live in PlayingState, which only reads this state.
+pub struct Simulation {
+    pub level: String,
+    pub settings: LevelSettings,
+    pub me: usize,
+    pub map: GameMap,
+    pub enemies: Enemies,
+    pub towers: Towers,
+    pub waves: Waves,
+    pub players: HashMap<usize, Player>,
+    pub projectiles: Projectiles,
+    pub cards: CardSpecs,
+    pub events: GameEvents,
+    pub seed: u64,
+    // started from the debug menu with every card
+    pub debug: bool,
+    pub rng: SimRng,
+    pub time: f32,
+}
+
+impl Simulation {
+    pub fn new(level: &str, debug: bool, seed: u64) -> Self {
+        let mut rng = SimRng::new(seed);
+        let settings = load_level_specs::<LevelSettings>(level, "level");
+        let map = GameMap::new(level, settings.maze, &mut rng);
+        let enemies = Enemies::new();
+        let towers = Towers::new(level);
+        let waves = Waves::new(level);
+        let projectiles = Projectiles::new();
+        let events = GameEvents::new();
+        let cards = CardSpecs::new(&towers);
+        let mut players = HashMap::new();
+        let me = 42;
+        let player = if debug {
+            Player::debug(me, &cards, &mut rng)
+        } else {
+            let player_settings = load_level_specs::<PlayerSettings>(level, "player");
+            cards.validate_player(level, &player_settings);
+            Player::new(me, &player_settings, &mut rng)
+        };
+        players.insert(me, player);
+
+        let sim = Self {
+            level: level.to_string(),
+            settings,
+            me,
+            map,
+            enemies,
+            towers,
+            waves,
+            players,
+            projectiles,
+            cards,
+            events,
+            seed,
+            debug,
+            rng,
+            time: 0.0,
+        };
+        Waves::validate(&sim);
+        return sim;
+    }
+
+    pub fn time(&self) -> f32 {
+        return self.time;
+    }
+
+    pub fn player_mut(&mut self) -> &mut Player {
+        self.players.get_mut(&self.me).unwrap()
+    }
+
+    pub fn player(&self) -> &Player {
+        self.players.get(&self.me).unwrap()
+    }
+
+    pub fn draw_cards(&mut self, n: usize) {
+        let player = self.players.get_mut(&self.me).unwrap();
+        player.deck.draw(n, &mut self.rng);
+    }
+
+    pub fn card_used(&mut self, slot: usize) {
+        let player = self.players.get_mut(&self.me).unwrap();
+        if let Some(card) = player.deck.hand.get(slot) {
+            self.events
+                .publish(GameEvent::CardPlayed { card: card.clone() });
+        }
+        player.deck.card_used(slot, &mut self.rng);
+    }
+
+    pub fn earn_gold(&mut self, player: usize, gold: usize) {
+        if gold == 0 {
+            return;
+        }
+        if let Some(p) = self.players.get_mut(&player) {
+            p.gold += gold;
+            self.events.publish(GameEvent::GoldEarned { player, gold });
+        }
+    }
+
+    pub fn outcome(&self) -> Option<Outcome> {
+        if self.player().hp <= 0 {
+            return Some(Outcome::Lost);
+        }
+        if self.waves.status == WaveStatus::LevelFinished {
+            return Some(Outcome::Won);
+        }
+        return None;
+    }
+
+    pub fn tick(&mut self) {
+        self.time += 1.0;
+        Player::tick(self);
+        Waves::tick(self);
+        Enemies::tick(self);
+        Towers::tick(self);
+        Projectiles::tick(self);
+        if self.waves.status == WaveStatus::WaveFinished {
+            self.waves.status = WaveStatus::Waiting(WAVE_COUNTDOWN);
+        }
+        if self.waves.status == WaveStatus::Ready {
+            Waves::start_next(self);
+        }
+    }
+
+    // Runs the game without any player input until it ends or max_ticks have passed.
+    pub fn run(&mut self, max_ticks: usize) -> Option<Outcome> {
+        for _ in 0..max_ticks {
+            if let Some(outcome) = self.outcome() {
+                return Some(outcome);
+            }
+            self.tick();
+            // nobody is listening when running without a window
+            self.events.drain();
+        }
+        return self.outcome();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Endless mode makes up its waves with the simulation's rng, with enough hp the
+    // player survives the scripted waves and loses to the generated ones
+    fn play(seed: u64) -> (Option<Outcome>, Simulation) {
+        let mut sim = Simulation::new("endless", false, seed);
+        sim.player_mut().hp = 100;
+        let outcome = sim.run(60 * 60 * 60);
+        return (outcome, sim);
+    }
+
+    fn enemies(sim: &Simulation) -> Vec<String> {
+        return sim
+            .enemies
+            .enemies
+            .iter()
+            .map(|(id, e)| format!("{} {} {} {:?}", id, e.stats.name, e.health, e.position))
+            .collect();
+    }
+
+    #[test]
+    fn same_seed_plays_the_same_game() {
+        let (outcome, sim) = play(7);
+        let (other_outcome, other) = play(7);
+        assert_eq!(outcome, Some(Outcome::Lost));
+        assert!(sim.waves.waves.len() > sim.waves.scripted);
+        assert_eq!(other_outcome, outcome);
+        assert_eq!(other.time(), sim.time());
+        assert_eq!(other.rng.words, sim.rng.words);
+        assert_eq!(other.waves.waves.len(), sim.waves.waves.len());
+        assert_eq!(enemies(&other), enemies(&sim));
+    }
+
+    #[test]
+    fn other_seed_plays_another_game() {
+        let (_, sim) = play(7);
+        let (_, other) = play(8);
+        let generated = |sim: &Simulation| format!("{:?}", &sim.waves.waves[sim.waves.scripted..]);
+        assert_ne!(generated(&other), generated(&sim));
+        assert!(other.time() != sim.time() || enemies(&other) != enemies(&sim));
+    }
+}
@@ -0,0 +1,59 @@
+use crate::game_events::GameEvent;
+use std::collections::HashMap;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Statistics {
+    pub kills: usize,
+    pub leaks: usize,
+    pub towers_built: usize,
+    pub towers_sold: usize,
+    pub cards_played: usize,
+    pub waves_cleared: usize,
+    pub gold_earned: usize,
+    pub kills_by_tower: HashMap<usize, usize>,
+}
+
+impl Statistics {
+    pub fn new() -> Self {
+        return Self {
+            kills: 0,
+            leaks: 0,
+            towers_built: 0,
+            towers_sold: 0,
+            cards_played: 0,
+            waves_cleared: 0,
+            gold_earned: 0,
+            kills_by_tower: HashMap::new(),
+        };
+    }
+
+    pub fn on_event(&mut self, event: &GameEvent) {
+        match event {
+            GameEvent::EnemyKilled { by_tower, .. } => {
+                self.kills += 1;
+                if let Some(tower) = by_tower {
+                    *self.kills_by_tower.entry(*tower).or_insert(0) += 1;
+                }
+            }
+            GameEvent::EnemyLeaked { .. } => self.leaks += 1,
+            GameEvent::TowerBuilt { .. } => self.towers_built += 1,
+            GameEvent::TowerSold { .. } => self.towers_sold += 1,
+            GameEvent::CardPlayed { .. } => self.cards_played += 1,
+            GameEvent::WaveCleared { .. } => self.waves_cleared += 1,
+            GameEvent::GoldEarned { gold, .. } => self.gold_earned += gold,
+            _ => {}
+        }
+    }
+
+    pub fn info(&self) -> String {
+        return format!(
+            "Waves cleared: {}\nEnemies killed: {}\nEnemies leaked: {}\nTowers built: {}\nCards played: {}\nGold earned: {}",
+            self.waves_cleared,
+            self.kills,
+            self.leaks,
+            self.towers_built,
+            self.cards_played,
+            self.gold_earned,
+        );
+    }
+}
@@ -3,29 +3,96 @@ use crate::debuffs::Debuff;
 use crate::enemies::Enemies;
 use crate::map::GameMap;
 use crate::projectiles::{Projectile, Projectiles};
-use crate::tower_stats::TowerStats;
+use crate::tower_stats::{TowerStats, TowerUpgrade};
 use std::collections::HashMap;
 use std::rc::Rc;
 
+// Which enemy in range a tower shoots at
+#[derive(Eq, PartialEq, Hash, Copy, Clone, Debug, Serialize, Deserialize)]
+pub enum TargetingMode {
+    // closest to the goal
+    First,
+    // furthest from the goal
+    Last,
+    Strongest,
+    Weakest,
+    // closest to the tower
+    Closest,
+    Fastest,
+}
+
+impl Default for TargetingMode {
+    fn default() -> Self {
+        return TargetingMode::Weakest;
+    }
+}
+
+impl TargetingMode {
+    pub fn next(&self) -> Self {
+        return match self {
+            TargetingMode::First => TargetingMode::Last,
+            TargetingMode::Last => TargetingMode::Strongest,
+            TargetingMode::Strongest => TargetingMode::Weakest,
+            TargetingMode::Weakest => TargetingMode::Closest,
+            TargetingMode::Closest => TargetingMode::Fastest,
+            TargetingMode::Fastest => TargetingMode::First,
+        };
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct Tower {
     pub id: usize,
-    cooldown: usize,
+    // name of the TowerStats in tower.ron
+    pub kind: String,
+    pub owner: usize,
+    pub targeting: TargetingMode,
+    // gold paid for building, buffing and upgrading this tower, the base for sell refunds
+    pub invested: usize,
+    // the branch taken at every level of the upgrade tree
+    pub upgrades: Vec<usize>,
+    // the stats of the kind with the upgrades applied, before buffs
+    pub stats: TowerStats,
+    // the branches the next upgrade can take
+    pub next_upgrades: Vec<TowerUpgrade>,
+    pub cooldown: usize,
     pub map_position: (usize, usize),
     pub buffs: HashMap<BuffType, Buff>,
 }
 
 impl Tower {
-    pub fn new(map_position: (usize, usize)) -> Self {
+    pub fn new(stats: &TowerStats, map_position: (usize, usize), owner: usize) -> Self {
         let buffs = HashMap::new();
+        // the upgrade tree is kept apart so it isn't copied along with the stats every tick
+        let mut stats = stats.clone();
+        let next_upgrades = std::mem::replace(&mut stats.upgrades, vec![]);
         return Self {
             id: 0,
+            kind: stats.name.clone(),
+            owner,
+            targeting: stats.targeting,
+            invested: 0,
+            upgrades: vec![],
+            stats,
+            next_upgrades,
             map_position,
             cooldown: 0,
             buffs,
         };
     }
 
+    // Takes a branch of the upgrade tree, false if there is no such branch
+    pub fn upgrade(&mut self, branch: usize) -> bool {
+        if branch >= self.next_upgrades.len() {
+            return false;
+        }
+        let upgrade = std::mem::replace(&mut self.next_upgrades, vec![]).swap_remove(branch);
+        upgrade.apply(&mut self.stats);
+        self.next_upgrades = upgrade.upgrades;
+        self.upgrades.push(branch);
+        return true;
+    }
+
     pub fn aura_level(&self) -> usize {
         if let Some(aura) = self.buffs.get(&BuffType::Aura) {
             return aura.level;
@@ -59,37 +126,132 @@ impl Tower {
     pub fn tick(
         &mut self,
         enemies: &Enemies,
+        map: &GameMap,
         projectiles: &mut Projectiles,
         stats: &TowerStats,
         aura_buffs: &HashMap<BuffType, Buff>,
     ) {
         self.cooldown = self.cooldown.saturating_sub(1);
-        if let Some(enemy_id) = enemies.weakest_enemy_in_range(
-            stats.range,
-            GameMap::tile_center(self.map_position.0, self.map_position.1),
-        ) {
-            if self.cooldown == 0 {
-                let mut projectile = Projectile::new(
-                    GameMap::tile_center(self.map_position.0, self.map_position.1),
-                    self.id,
-                    enemy_id,
-                    stats.damage,
-                    stats.projectile_speed,
-                );
-                self.add_projectile_buffs(&mut projectile, aura_buffs);
-                projectiles.spawn(projectile);
-                // 60 sec per minute / rpm * 60 ticks per second
-                self.cooldown = 3600 / stats.rpm;
+        if self.cooldown > 0 {
+            return;
+        }
+        let position = GameMap::tile_center(self.map_position.0, self.map_position.1);
+        let targets = enemies.targets_in_range(stats.range, position, self.targeting, map);
+        if targets.is_empty() {
+            return;
+        }
+        for enemy_id in targets.into_iter().take(stats.shots) {
+            let mut projectile = Projectile::new(
+                position,
+                self.id,
+                self.owner,
+                enemy_id,
+                stats.damage,
+                stats.projectile_speed,
+            );
+            projectile.behavior = stats.projectile.clone();
+            projectile.disp = stats.projectile_img;
+            projectile.range = stats.range;
+            for debuff in stats.debuffs.iter() {
+                projectile.add_debuff(debuff.clone());
             }
+            self.add_projectile_buffs(&mut projectile, aura_buffs);
+            projectiles.spawn(projectile);
         }
+        // 60 sec per minute / rpm * 60 ticks per second
+        self.cooldown = 3600 / stats.rpm;
     }
 
     pub fn add_projectile_buffs(&self, p: &mut Projectile, aura_buffs: &HashMap<BuffType, Buff>) {
-        if let Some(buff) = self.get_buffs().get(&BuffType::Freeze) {
-            p.add_debuff(Debuff::new(buff.clone()));
-        }
-        for (_, buff) in aura_buffs {
-            p.add_debuff(Debuff::new(buff.clone()));
+        for buff in self.get_buffs().values().chain(aura_buffs.values()) {
+            if let Some(debuff) = Debuff::from_buff(buff) {
+                p.add_debuff(debuff);
+            }
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::assets::ImgID;
+    use crate::debuffs::DebuffType;
+    use crate::projectiles::ProjectileBehavior;
+    use crate::utils::load_specs;
+
+    fn tower(kind: &str) -> Tower {
+        let stats = load_specs::<TowerStats>("towers")
+            .into_iter()
+            .find(|t| t.name == kind)
+            .unwrap();
+        return Tower::new(&stats, (0, 0), 0);
+    }
+
+    #[test]
+    fn upgrades_add_to_the_stats() {
+        let mut archer = tower("Archer");
+        assert_eq!(archer.next_upgrades.len(), 2);
+        assert!(archer.upgrade(1));
+        assert_eq!(archer.stats.damage, 35);
+        assert_eq!(archer.stats.range, 210.0);
+        assert_eq!(archer.stats.rpm, 140);
+        assert_eq!(archer.stats.projectile_speed, 18.0);
+        // the optional ones replace them
+        assert_eq!(archer.stats.img, ImgID::Sniper);
+        assert_eq!(
+            archer.stats.projectile,
+            ProjectileBehavior::Pierce { hits: 3 }
+        );
+        assert!(archer.upgrade(0));
+        assert_eq!(archer.stats.damage, 75);
+        assert_eq!(
+            archer.stats.projectile,
+            ProjectileBehavior::Pierce { hits: 5 }
+        );
+        // untouched by the upgrades
+        assert_eq!(archer.stats.img, ImgID::Sniper);
+        assert_eq!(archer.stats.shots, 1);
+        assert_eq!(archer.upgrades, vec![1, 0]);
+        assert_eq!(archer.stats.upgraded, vec!["Longbow", "Marksman"]);
+        assert!(archer
+            .stats
+            .info()
+            .starts_with("Archer > Longbow > Marksman\n"));
+    }
+
+    #[test]
+    fn upgrades_follow_their_branch() {
+        let mut archer = tower("Archer");
+        assert!(archer.upgrade(0));
+        let next: Vec<_> = archer.next_upgrades.iter().map(|u| &u.name).collect();
+        assert_eq!(next, vec!["Volley"]);
+        assert!(archer.upgrade(0));
+        assert_eq!((archer.stats.rpm, archer.stats.shots), (180, 4));
+        // the end of the tree
+        assert!(archer.next_upgrades.is_empty());
+        assert!(!archer.upgrade(0));
+        assert_eq!(archer.upgrades, vec![0, 0]);
+    }
+
+    #[test]
+    fn missing_branches_change_nothing() {
+        let mut cannon = tower("Cannon");
+        assert!(!cannon.upgrade(2));
+        assert!(cannon.upgrades.is_empty());
+        assert_eq!(cannon.next_upgrades.len(), 2);
+        assert_eq!(cannon.stats.rpm, 40);
+    }
+
+    #[test]
+    fn upgrade_debuffs_come_on_top() {
+        let mut cannon = tower("Cannon");
+        assert!(cannon.upgrade(1));
+        let stuns: Vec<_> = cannon
+            .stats
+            .debuffs
+            .iter()
+            .map(|d| (d.kind, d.duration))
+            .collect();
+        assert_eq!(stuns, vec![(DebuffType::Stun, 15), (DebuffType::Stun, 30)]);
+    }
+}
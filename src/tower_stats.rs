@@ -1,15 +1,112 @@
+use crate::assets::ImgID;
 use crate::buffs::{Buff, BuffType};
-use crate::tower::Tower;
+use crate::debuffs::Debuff;
+use crate::projectiles::ProjectileBehavior;
+use crate::tower::{TargetingMode, Tower};
 use std::collections::HashMap;
 
+// Upgrades are bought with the number keys 1 to 9
+pub const MAX_UPGRADE_BRANCHES: usize = 9;
+
 #[derive(Debug, Deserialize, Clone)]
 pub struct TowerStats {
+    // towers and BuildTower cards refer to their kind by this name
+    pub name: String,
+    pub img: ImgID,
     pub damage: usize,
     pub projectile_speed: f32,
     pub range: f32,
     pub rpm: usize,
     pub price: usize,
     pub mana_cost: usize,
+    #[serde(default)]
+    pub projectile: ProjectileBehavior,
+    // what new towers of this kind shoot at, players can change it per tower
+    #[serde(default)]
+    pub targeting: TargetingMode,
+    // applied to every enemy the projectiles hit
+    #[serde(default)]
+    pub debuffs: Vec<Debuff>,
+    #[serde(default = "arrow")]
+    pub projectile_img: ImgID,
+    // number of enemies shot at at once
+    #[serde(default = "one")]
+    pub shots: usize,
+    // the branches a tower of this kind can be upgraded into
+    #[serde(default)]
+    pub upgrades: Vec<TowerUpgrade>,
+    // names of the upgrades that were applied
+    #[serde(skip)]
+    pub upgraded: Vec<String>,
+}
+
+// A tower kind from resources/rons/towers.ron that a level lets players build,
+// optionally for a different price
+#[derive(Debug, Deserialize, Clone)]
+pub struct TowerOffer {
+    pub kind: String,
+    #[serde(default)]
+    pub price: Option<usize>,
+}
+
+fn one() -> usize {
+    return 1;
+}
+
+fn arrow() -> ImgID {
+    return ImgID::Arrow;
+}
+
+// A node in the upgrade tree of a tower kind. Its values are added to the stats
+// of the tower, the optional ones replace them.
+#[derive(Debug, Deserialize, Clone)]
+pub struct TowerUpgrade {
+    pub name: String,
+    pub price: usize,
+    #[serde(default)]
+    pub mana_cost: usize,
+    #[serde(default)]
+    pub img: Option<ImgID>,
+    #[serde(default)]
+    pub damage: usize,
+    #[serde(default)]
+    pub range: f32,
+    #[serde(default)]
+    pub rpm: usize,
+    #[serde(default)]
+    pub projectile_speed: f32,
+    #[serde(default)]
+    pub shots: usize,
+    #[serde(default)]
+    pub projectile: Option<ProjectileBehavior>,
+    #[serde(default)]
+    pub projectile_img: Option<ImgID>,
+    #[serde(default)]
+    pub debuffs: Vec<Debuff>,
+    // the branches that can follow this upgrade
+    #[serde(default)]
+    pub upgrades: Vec<TowerUpgrade>,
+}
+
+impl TowerUpgrade {
+    pub fn apply(&self, stats: &mut TowerStats) {
+        if let Some(img) = self.img {
+            stats.img = img;
+        }
+        stats.damage += self.damage;
+        stats.range += self.range;
+        stats.rpm += self.rpm;
+        stats.projectile_speed += self.projectile_speed;
+        stats.shots += self.shots;
+        if let Some(projectile) = &self.projectile {
+            stats.projectile = projectile.clone();
+        }
+        if let Some(img) = self.projectile_img {
+            stats.projectile_img = img;
+        }
+        stats.debuffs.extend(self.debuffs.iter().cloned());
+        stats.upgraded.push(self.name.clone());
+    }
 }
 
 impl TowerStats {
@@ -40,8 +137,14 @@ impl TowerStats {
     }
 
     pub fn info(&self) -> String {
+        let mut name = self.name.clone();
+        for upgrade in self.upgraded.iter() {
+            name += " > ";
+            name += upgrade;
+        }
         return format!(
-            "Damage: {}\nRange: {}\nRPM: {}",
+            "{}\nDamage: {}\nRange: {}\nRPM: {}",
+            &name,
             &self.damage.to_string(),
             &self.range.to_string(),
             &self.rpm.to_string(),
@@ -1,37 +1,77 @@
 use crate::algebra::{Point, Vector};
-use crate::assets::{Data, ImgID};
+use crate::assets::Data;
 use crate::buffs::{Buff, BuffStats, BuffType};
+use crate::hex::Hex;
+use crate::level::level_path;
 use crate::map::GameMap;
 use crate::playing_state::PlayingState;
+use crate::simulation::Simulation;
 use crate::tower::Tower;
-use crate::tower_stats::TowerStats;
+use crate::tower_stats::{TowerOffer, TowerStats, TowerUpgrade, MAX_UPGRADE_BRANCHES};
 use crate::utils::buff_to_img;
-use crate::utils::load_specs;
+use crate::utils::{invalid_spec, load_level_specs, load_specs};
 use ggez::graphics;
 use ggez::{Context, GameResult};
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::rc::Rc;
 
 pub struct Towers {
-    pub stats: TowerStats,
+    // every kind of tower this level offers, by name
+    pub stats: HashMap<String, TowerStats>,
+    // every kind in towers.ron, levels only offer some of them
+    pub catalogue: HashSet<String>,
     pub buff_stats: HashMap<BuffType, Rc<BuffStats>>,
-    built: HashMap<usize, Tower>,
+    pub built: BTreeMap<usize, Tower>,
     position_to_towerid: HashMap<(usize, usize), usize>,
-    next_tower_id: usize,
+    pub next_tower_id: usize,
 }
 
 impl Towers {
-    pub fn new() -> Self {
-        let stats = load_specs::<TowerStats>("tower")[0].clone();
+    fn check_branches(name: &str, upgrades: &[TowerUpgrade]) {
+        if upgrades.len() > MAX_UPGRADE_BRANCHES {
+            invalid_spec(
+                "resources/rons/towers.ron",
+                &format!(
+                    "{} has more than {} upgrade branches",
+                    name, MAX_UPGRADE_BRANCHES
+                ),
+            );
+        }
+        for upgrade in upgrades.iter() {
+            Towers::check_branches(&upgrade.name, &upgrade.upgrades);
+        }
+    }
+
+    pub fn new(level: &str) -> Self {
+        let mut catalogue = HashMap::new();
+        for tower in load_specs::<TowerStats>("towers") {
+            Towers::check_branches(&tower.name, &tower.upgrades);
+            catalogue.insert(tower.name.clone(), tower);
+        }
+        let mut stats = HashMap::new();
+        for offer in load_level_specs::<Vec<TowerOffer>>(level, "tower") {
+            let mut tower = match catalogue.get(&offer.kind) {
+                Some(tower) => tower.clone(),
+                None => invalid_spec(
+                    &level_path(level, "tower"),
+                    &format!("unknown tower kind {}", offer.kind),
+                ),
+            };
+            if let Some(price) = offer.price {
+                tower.price = price;
+            }
+            stats.insert(offer.kind, tower);
+        }
         let buffs = load_specs::<BuffStats>("buffs");
         let mut buff_stats = HashMap::new();
         for buff in buffs.iter() {
             buff_stats.insert(buff.kind, Rc::new(buff.clone()));
         }
-        let built = HashMap::new();
+        let built = BTreeMap::new();
         let position_to_towerid = HashMap::new();
         return Self {
             buff_stats,
+            catalogue: catalogue.keys().cloned().collect(),
             stats,
             built,
             position_to_towerid,
@@ -39,12 +79,31 @@ impl Towers {
         };
     }
 
-    pub fn spawn(&mut self, mut tower: Tower) {
-        tower.id = self.next_tower_id;
+    pub fn kind(&self, kind: &str) -> Option<&TowerStats> {
+        return self.stats.get(kind);
+    }
+
+    // The upgrade the tower at x, y would get when taking the given branch
+    pub fn upgrade_at(&self, x: usize, y: usize, branch: usize) -> Option<&TowerUpgrade> {
+        let tower = self.get_tower(x, y)?;
+        return tower.next_upgrades.get(branch);
+    }
+
+    pub fn spawn(&mut self, mut tower: Tower) -> usize {
+        let id = self.next_tower_id;
+        tower.id = id;
         self.next_tower_id += 1;
         self.position_to_towerid
             .insert(tower.map_position.clone(), tower.id);
         self.built.insert(tower.id, tower);
+        return id;
+    }
+
+    pub fn restore(&mut self, tower: Tower) {
+        self.next_tower_id = self.next_tower_id.max(tower.id + 1);
+        self.position_to_towerid
+            .insert(tower.map_position.clone(), tower.id);
+        self.built.insert(tower.id, tower);
     }
 
     pub fn buffs_at(&self, x: usize, y: usize) -> Option<Vec<Buff>> {
@@ -68,21 +127,27 @@ impl Towers {
             return Some(TowerStats::get_buffed_stats(
                 &tower,
                 auras.get(&tower.id).unwrap_or(&default),
-                &self.stats,
+                &tower.stats,
             ));
         }
         return None;
     }
 
+    // Gold the owner gets back when selling the tower at x, y
+    pub fn refund_at(&self, x: usize, y: usize, percent: usize) -> Option<usize> {
+        let tower = self.get_tower(x, y)?;
+        return Some(tower.invested * percent / 100);
+    }
+
     pub fn has_building(&self, x: usize, y: usize) -> bool {
         return self.position_to_towerid.contains_key(&(x, y));
     }
 
-    pub fn remove_tower(&mut self, x: usize, y: usize) {
-        if let Some(id) = self.position_to_towerid.get(&(x, y)) {
-            self.built.remove(id);
-            self.position_to_towerid.remove(&(x, y));
+    pub fn remove_tower(&mut self, x: usize, y: usize) -> Option<Tower> {
+        if let Some(id) = self.position_to_towerid.remove(&(x, y)) {
+            return self.built.remove(&id);
         }
+        return None;
     }
 
     pub fn add_buff_at_pos(&mut self, x: usize, y: usize, buff_type: BuffType) {
@@ -107,10 +172,10 @@ impl Towers {
     }
 
     pub fn draw(state: &PlayingState, data: &Data, ctx: &mut Context) -> GameResult<()> {
-        for (_id, t) in state.towers.built.iter() {
+        for (_id, t) in state.sim.towers.built.iter() {
             graphics::draw(
                 ctx,
-                data.get_i(&ImgID::Archer),
+                data.get_i(&t.stats.img),
                 graphics::DrawParam::default()
                     .dest(
                         state
@@ -171,40 +236,31 @@ impl Towers {
     }
 
     pub fn affected_by_aura(&self, t: &Tower) -> Vec<usize> {
-        let mut nn = Vec::new();
-        for (x, y) in GameMap::tile_potential_neighbors(
-            t.map_position.0 as isize,
-            t.map_position.1 as isize,
-            t.aura_level(),
-        )
-        .iter()
-        {
-            if *x >= 0 && *y >= 0 {
-                if let Some(id) = self.position_to_towerid.get(&(*x as usize, *y as usize)) {
-                    if *id != t.id {
-                        nn.push(*id);
-                    }
-                };
-            }
-        }
-        return nn;
+        let center = Hex::from_offset(t.map_position.0 as isize, t.map_position.1 as isize);
+        return self
+            .built
+            .values()
+            .filter(|other| {
+                let (x, y) = other.map_position;
+                let distance = center.distance(Hex::from_offset(x as isize, y as isize));
+                return other.id != t.id && distance <= t.aura_level();
+            })
+            .map(|other| other.id)
+            .collect();
     }
 
-    pub fn tick(state: &mut PlayingState) {
+    pub fn tick(sim: &mut Simulation) {
         let mut auras = HashMap::new();
-        for (_id, t) in state.towers.built.iter() {
-            auras = state.towers.cast_aura(auras, t);
+        for (_id, t) in sim.towers.built.iter() {
+            auras = sim.towers.cast_aura(auras, t);
         }
         let default: HashMap<BuffType, Buff> = HashMap::new();
-        for (id, t) in state.towers.built.iter_mut() {
+        for (id, t) in sim.towers.built.iter_mut() {
             t.tick(
-                &state.enemies,
-                &mut state.projectiles,
-                &TowerStats::get_buffed_stats(
-                    &t,
-                    auras.get(id).unwrap_or(&default),
-                    &state.towers.stats,
-                ),
+                &sim.enemies,
+                &sim.map,
+                &mut sim.projectiles,
+                &TowerStats::get_buffed_stats(&t, auras.get(id).unwrap_or(&default), &t.stats),
                 &auras.get(id).unwrap_or(&default),
             )
         }
@@ -2,6 +2,7 @@ use crate::algebra::Point;
 use crate::assets::Data;
 use crate::assets::ImgID;
 use crate::buffs::BuffType;
+use crate::level::level_path;
 use ggez::graphics::{Scale, Text, TextFragment};
 use ron;
 use ron::de::from_reader;
@@ -20,6 +21,10 @@ pub fn text(data: &Data, s: &str) -> Text {
     return text;
 }
 
+pub fn lerp(from: Point, to: Point, t: f32) -> Point {
+    return from + (to - from) * t;
+}
+
 pub fn move_to(pos: Point, target: Point, speed: f32) -> (Point, bool) {
     assert!(speed > 0.0);
     let dir = target - pos;
@@ -43,12 +48,31 @@ pub fn load_specs<T>(name: &str) -> Vec<T>
 where
     for<'de> T: serde::Deserialize<'de>,
 {
-    let spec_path = format!("resources/rons/{}.ron", name);
-    let f = File::open(&spec_path).expect(&format!("Failed opening {}.ron", name));
+    return load_ron(&format!("resources/rons/{}.ron", name));
+}
+
+pub fn load_level_specs<T>(level: &str, name: &str) -> T
+where
+    for<'de> T: serde::Deserialize<'de>,
+{
+    return load_ron(&level_path(level, name));
+}
+
+// Specs that parsed but make no sense stop the game while loading, not during play
+pub fn invalid_spec(spec: &str, message: &str) -> ! {
+    println!("Invalid {}: {}", spec, message);
+    ::std::process::exit(1);
+}
+
+fn load_ron<T>(spec_path: &str) -> T
+where
+    for<'de> T: serde::Deserialize<'de>,
+{
+    let f = File::open(spec_path).expect(&format!("Failed opening {}", spec_path));
     let specs = match from_reader(f) {
         Ok(x) => x,
         Err(e) => {
-            println!("Failed to load {}.ron: {}", name, e);
+            println!("Failed to load {}: {}", spec_path, e);
             ::std::process::exit(1);
         }
     };
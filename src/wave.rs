@@ -1,29 +1,129 @@
-use crate::assets::ImgID;
 use crate::enemy::Enemy;
-use crate::map::GameMap;
-use crate::playing_state::PlayingState;
-use crate::utils::load_specs;
+use crate::game_events::GameEvent;
+use crate::level::{level_path, Endless};
+use crate::simulation::Simulation;
+use crate::utils::{invalid_spec, load_level_specs};
+use rand::seq::SliceRandom;
+use rand::Rng;
 
-#[derive(Debug, Deserialize, Clone)]
+// Ticks between clearing a wave and the next one starting on its own
+pub const WAVE_COUNTDOWN: usize = 5 * 60;
+// Number of upcoming waves the preview panel shows
+pub const PREVIEW_WAVES: usize = 2;
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(from = "WaveFile")]
 pub struct WaveSpec {
-    pub speed: f32,
-    pub health: usize,
-    pub enemy_count: usize,
+    // all groups spawn in parallel, each starting at its own offset
+    pub groups: Vec<WaveGroup>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct WaveGroup {
+    // name of the archetype in enemies.ron
+    pub enemy: String,
+    pub count: usize,
     pub spawn_delay: usize,
-    pub size: f32,
-    pub color: (f32, f32, f32),
-    pub img: ImgID,
+    // ticks after the wave started before the first enemy of the group spawns
+    #[serde(default)]
+    pub start: usize,
+    // indices into GameMap::spawn_points, an empty list uses all of them
+    #[serde(default)]
+    pub spawns: Vec<usize>,
+    #[serde(default)]
+    pub spawn_mode: SpawnMode,
+    // percent of the archetype's health and speed the enemies get
+    #[serde(default = "hundred")]
+    pub health: usize,
+    #[serde(default = "hundred")]
+    pub speed: usize,
+}
+
+fn hundred() -> usize {
+    return 100;
+}
+
+// A wave in waves.ron, either a list of groups or a single group written out
+// directly like in the older files
+#[derive(Deserialize)]
+struct WaveFile {
+    #[serde(default)]
+    groups: Vec<WaveGroup>,
+    #[serde(default)]
+    enemy: String,
+    #[serde(default)]
+    enemy_count: usize,
+    #[serde(default)]
+    spawn_delay: usize,
+    #[serde(default)]
+    spawns: Vec<usize>,
+    #[serde(default)]
+    spawn_mode: SpawnMode,
+}
+
+impl From<WaveFile> for WaveSpec {
+    fn from(file: WaveFile) -> Self {
+        let mut groups = file.groups;
+        if groups.is_empty() {
+            groups.push(WaveGroup {
+                enemy: file.enemy,
+                count: file.enemy_count,
+                spawn_delay: file.spawn_delay,
+                start: 0,
+                spawns: file.spawns,
+                spawn_mode: file.spawn_mode,
+                health: 100,
+                speed: 100,
+            });
+        }
+        return Self { groups };
+    }
+}
+
+#[derive(Eq, PartialEq, Hash, Copy, Clone, Debug, Serialize, Deserialize)]
+pub enum SpawnMode {
+    // one enemy at a time, cycling through the spawn points
+    RoundRobin,
+    // one enemy at a time, at a random spawn point
+    Random,
+    // one enemy at every spawn point at once
+    Simultaneous,
+}
+
+impl Default for SpawnMode {
+    fn default() -> Self {
+        return SpawnMode::RoundRobin;
+    }
 }
 
 pub struct Waves {
+    // the next wave to start
     pub id: usize,
     pub waves: Vec<WaveSpec>,
+    // number of waves from waves.ron, the ones after it were generated in endless mode
+    pub scripted: usize,
     pub status: WaveStatus,
+    // waves that are spawning or have enemies left, more than one if called early
+    pub active: Vec<ActiveWave>,
+}
+
+#[derive(Eq, PartialEq, Clone, Debug, Serialize, Deserialize)]
+pub struct ActiveWave {
+    pub id: usize,
+    // ticks since the wave started
+    pub time: usize,
+    // progress of every group of the wave
+    pub groups: Vec<GroupProgress>,
+}
+
+#[derive(Eq, PartialEq, Clone, Debug, Default, Serialize, Deserialize)]
+pub struct GroupProgress {
+    pub spawned: usize,
     pub next_spawn: usize,
-    pub enemy_count: usize,
+    pub next_spawn_point: usize,
 }
 
-#[derive(Eq, PartialEq, Hash, Copy, Clone, Debug)]
+#[derive(Eq, PartialEq, Hash, Copy, Clone, Debug, Serialize, Deserialize)]
 pub enum WaveStatus {
     WaveFinished,
     Ongoing,
@@ -32,39 +132,277 @@ pub enum WaveStatus {
     LevelFinished,
 }
 impl Waves {
-    pub fn new() -> Self {
+    pub fn new(level: &str) -> Self {
+        let waves: Vec<WaveSpec> = load_level_specs(level, "waves");
         return Self {
             id: 0,
-            waves: load_specs("waves"),
-            status: WaveStatus::Waiting(5 * 60),
-            next_spawn: 0,
-            enemy_count: 0,
+            scripted: waves.len(),
+            waves,
+            status: WaveStatus::Waiting(WAVE_COUNTDOWN),
+            active: vec![],
         };
     }
 
-    fn current_wave(&self) -> WaveSpec {
-        self.waves.get(self.id).unwrap().clone()
+    // Stops loading the level if waves.ron doesn't fit the rest of it
+    pub fn validate(sim: &Simulation) {
+        let spec = level_path(&sim.level, "waves");
+        for (id, wave) in sim.waves.waves.iter().enumerate() {
+            for group in wave.groups.iter() {
+                if !sim.enemies.archetypes.contains_key(&group.enemy) {
+                    invalid_spec(
+                        &spec,
+                        &format!("wave {} uses unknown enemy {}", id, group.enemy),
+                    );
+                }
+                for spawn in group.spawns.iter() {
+                    if *spawn >= sim.map.spawn_points.len() {
+                        invalid_spec(
+                            &spec,
+                            &format!(
+                                "wave {} uses spawn point {}, but the map only has {}",
+                                id,
+                                spawn,
+                                sim.map.spawn_points.len()
+                            ),
+                        );
+                    }
+                }
+            }
+        }
+        if let Some(endless) = &sim.settings.endless {
+            Waves::validate_endless(sim, endless);
+        }
+    }
+
+    // Stops loading the level if the endless settings can't generate waves
+    fn validate_endless(sim: &Simulation, endless: &Endless) {
+        let spec = level_path(&sim.level, "level");
+        if endless.enemies.is_empty() {
+            invalid_spec(&spec, "endless mode needs enemies to pick from");
+        }
+        for enemy in endless.enemies.iter() {
+            if !sim.enemies.archetypes.contains_key(enemy) {
+                invalid_spec(&spec, &format!("endless mode uses unknown enemy {}", enemy));
+            }
+        }
+    }
+
+    // Waves can be called during the countdown and, to overlap them, while one is running
+    pub fn can_call_next(sim: &Simulation) -> bool {
+        match sim.waves.status {
+            WaveStatus::Waiting(_) => return true,
+            WaveStatus::Ongoing => return sim.waves.id < sim.waves.waves.len(),
+            _ => return false,
+        }
+    }
+
+    pub fn call_next(sim: &mut Simulation, player: usize) {
+        if !Waves::can_call_next(sim) {
+            return;
+        }
+        match sim.waves.status {
+            WaveStatus::Waiting(remaining) => {
+                Waves::pay_early_call_reward(sim, player, remaining);
+                sim.waves.status = WaveStatus::Waiting(0);
+            }
+            // there is no countdown to cut short while a wave runs, so overlapping
+            // waves earn nothing extra and can't be called every tick for rewards
+            _ => Waves::start_next(sim),
+        }
     }
 
-    fn is_finished(&self) -> bool {
-        self.waves.get(self.id).is_none()
+    fn pay_early_call_reward(sim: &mut Simulation, player: usize, remaining: usize) {
+        let seconds = remaining as f32 / 60.0;
+        let gold = (sim.settings.early_call_reward.gold * seconds) as usize;
+        let mana = sim.settings.early_call_reward.mana * seconds;
+        sim.earn_gold(player, gold);
+        if let Some(p) = sim.players.get_mut(&player) {
+            p.mana += mana;
+        }
     }
 
-    pub fn tick(state: &mut PlayingState) {
-        if state.waves.is_finished() {
-            state.waves.status = WaveStatus::LevelFinished;
+    pub fn start_next(sim: &mut Simulation) {
+        let id = sim.waves.id;
+        if id >= sim.waves.waves.len() {
             return;
         }
+        let groups = vec![GroupProgress::default(); sim.waves.waves[id].groups.len()];
+        sim.waves.active.push(ActiveWave {
+            id,
+            time: 0,
+            groups,
+        });
+        sim.waves.id += 1;
+        Waves::generate_upcoming(sim);
+        sim.waves.status = WaveStatus::Ongoing;
+        sim.events.publish(GameEvent::WaveStarted { wave: id });
+    }
 
-        let wave = state.waves.current_wave();
+    // Waves that were started and have no enemies left
+    pub fn cleared(&self) -> usize {
+        return self.id - self.active.len();
+    }
+
+    // The next waves that haven't started yet, with their index
+    pub fn upcoming(&self, n: usize) -> Vec<(usize, &WaveSpec)> {
+        return self
+            .waves
+            .iter()
+            .enumerate()
+            .skip(self.id)
+            .take(n)
+            .collect();
+    }
 
-        match state.waves.status {
+    // Returns the spawn points the next enemies of the group walk out of
+    fn next_spawn_points(
+        sim: &mut Simulation,
+        active: usize,
+        index: usize,
+        group: &WaveGroup,
+    ) -> Vec<usize> {
+        let spawns: Vec<usize> = if group.spawns.is_empty() {
+            (0..sim.map.spawn_points.len()).collect()
+        } else {
+            group.spawns.clone()
+        };
+        match group.spawn_mode {
+            SpawnMode::RoundRobin => {
+                let progress = &mut sim.waves.active[active].groups[index];
+                let spawn = spawns[progress.next_spawn_point % spawns.len()];
+                progress.next_spawn_point += 1;
+                return vec![spawn];
+            }
+            SpawnMode::Random => {
+                return vec![*spawns
+                    .choose(&mut sim.rng)
+                    .expect("I need to spawn zombies")];
+            }
+            SpawnMode::Simultaneous => return spawns,
+        }
+    }
+
+    // Wave clear bonus for every cleared wave and interest once on the gold every player has left
+    fn pay_wave_rewards(sim: &mut Simulation, waves: usize) {
+        let players: Vec<usize> = sim.players.keys().cloned().collect();
+        for player in players {
+            let mut gold = sim.settings.wave_clear_bonus * waves;
+            if let Some(interest) = &sim.settings.interest {
+                let banked = sim.players[&player].gold;
+                gold += (banked * interest.percent / 100).min(interest.max);
+            }
+            sim.earn_gold(player, gold);
+        }
+    }
+
+    // Endless mode always has the next waves ready, so they can be previewed and called
+    fn generate_upcoming(sim: &mut Simulation) {
+        if let Some(endless) = sim.settings.endless.clone() {
+            while sim.waves.waves.len() < sim.waves.id + PREVIEW_WAVES {
+                let wave = Waves::generate(sim, &endless);
+                sim.waves.waves.push(wave);
+            }
+        }
+    }
+
+    // A random wave for endless mode, the total enemy health grows with every wave
+    fn generate(sim: &mut Simulation, endless: &Endless) -> WaveSpec {
+        let n = sim.waves.waves.len() - sim.waves.scripted;
+        let growth = 1.0 + endless.growth as f32 / 100.0;
+        let budget = endless.budget as f32 * growth.powi(n as i32);
+        let group_count = sim.rng.gen_range(1, 4);
+        let mut groups = vec![];
+        for i in 0..group_count {
+            let enemy = endless
+                .enemies
+                .choose(&mut sim.rng)
+                .expect("checked in Waves::validate_endless")
+                .clone();
+            let archetype = sim.enemies.archetype(&enemy);
+            // later waves have tougher and faster enemies instead of only more of them
+            let mut health = 100 + 5 * n + sim.rng.gen_range(0, 20);
+            let speed = 100 + (2 * n).min(50) + sim.rng.gen_range(0, 10);
+            let share = budget / group_count as f32;
+            // the cast saturates once the budget grows out of range
+            let mut count = (share / (archetype.health * health / 100).max(1) as f32) as usize;
+            if count > 60 {
+                health = health.saturating_mul(count) / 60;
+                count = 60;
+            }
+            let spawn_mode = *[SpawnMode::RoundRobin, SpawnMode::Random]
+                .choose(&mut sim.rng)
+                .unwrap();
+            groups.push(WaveGroup {
+                enemy,
+                count: count.max(1),
+                spawn_delay: sim.rng.gen_range(10, 60),
+                start: i * sim.rng.gen_range(120, 360),
+                spawns: vec![],
+                spawn_mode,
+                health,
+                speed,
+            });
+        }
+        return WaveSpec { groups };
+    }
+
+    // Spawns the enemies of an active wave that are due, returns false once all are out
+    fn spawn_groups(sim: &mut Simulation, active: usize) -> bool {
+        let wave = sim.waves.waves[sim.waves.active[active].id].clone();
+        let time = sim.waves.active[active].time;
+        let mut spawning = false;
+        for (i, group) in wave.groups.iter().enumerate() {
+            if sim.waves.active[active].groups[i].spawned >= group.count {
+                continue;
+            }
+            spawning = true;
+            if time < group.start {
+                continue;
+            }
+            if sim.waves.active[active].groups[i].next_spawn == 0 {
+                for spawn in Waves::next_spawn_points(sim, active, i, group) {
+                    if sim.waves.active[active].groups[i].spawned >= group.count {
+                        break;
+                    }
+                    let base = sim.enemies.archetype(&group.enemy);
+                    let archetype = sim.enemies.archetype_with(
+                        &group.enemy,
+                        base.health.saturating_mul(group.health) / 100,
+                        base.speed * group.speed as f32 / 100.0,
+                    );
+                    let enemy = Enemy::new(spawn, &sim.map, archetype);
+                    let position = enemy.position;
+                    let enemy = sim.enemies.spawn(enemy);
+                    sim.events
+                        .publish(GameEvent::EnemySpawned { enemy, position });
+                    sim.waves.active[active].groups[i].spawned += 1;
+                }
+                sim.waves.active[active].groups[i].next_spawn = group.spawn_delay;
+            } else {
+                sim.waves.active[active].groups[i].next_spawn -= 1;
+            }
+        }
+        sim.waves.active[active].time += 1;
+        return spawning;
+    }
+
+    pub fn tick(sim: &mut Simulation) {
+        Waves::generate_upcoming(sim);
+        if sim.settings.endless.is_none()
+            && sim.waves.id >= sim.waves.waves.len()
+            && sim.waves.active.is_empty()
+        {
+            sim.waves.status = WaveStatus::LevelFinished;
+            return;
+        }
+
+        match sim.waves.status {
             WaveStatus::Waiting(ref mut a) => {
                 if *a > 0 {
                     *a -= 1;
                     return;
                 } else {
-                    state.waves.status = WaveStatus::Ready;
+                    sim.waves.status = WaveStatus::Ready;
                     return;
                 }
             }
@@ -73,29 +411,172 @@ impl Waves {
             }
             WaveStatus::Ongoing => {}
         }
-        if state.waves.enemy_count < wave.enemy_count {
-            if state.waves.next_spawn == 0 {
-                if state.waves.enemy_count < wave.enemy_count {
-                    let (x, y) = state
-                        .map
-                        .get_spawn_points()
-                        .pop()
-                        .expect("I need to spawn zombies");
-                    let pos = GameMap::tile_center(x, y);
-                    state.enemies.spawn(Enemy::new(pos, &wave));
-                    state.effects.fire(pos.x, pos.y);
-                    state.waves.enemy_count += 1;
-                    state.waves.next_spawn = wave.spawn_delay;
-                }
-            } else {
-                state.waves.next_spawn -= 1;
+        let mut spawning = false;
+        for active in 0..sim.waves.active.len() {
+            spawning |= Waves::spawn_groups(sim, active);
+        }
+        if !spawning && !sim.enemies.any_alive() {
+            let cleared: Vec<usize> = sim.waves.active.drain(..).map(|w| w.id).collect();
+            for wave in cleared.iter() {
+                sim.events.publish(GameEvent::WaveCleared { wave: *wave });
             }
-        } else {
-            if !state.enemies.any_alive() {
-                state.waves.id += 1;
-                state.waves.next_spawn = 0;
-                state.waves.enemy_count = 0;
-                state.waves.status = WaveStatus::WaveFinished;
+            Waves::pay_wave_rewards(sim, cleared.len());
+            sim.waves.status = WaveStatus::WaveFinished;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::level::{available_levels, EarlyCallReward, Interest};
+    use ron::de::from_str;
+
+    fn sim() -> Simulation {
+        let mut sim = Simulation::new("tutorial", false, 0);
+        sim.player_mut().gold = 0;
+        return sim;
+    }
+
+    #[test]
+    fn cleared_waves_pay_the_bonus_and_interest() {
+        let mut sim = sim();
+        sim.settings.wave_clear_bonus = 10;
+        sim.settings.interest = Some(Interest {
+            percent: 10,
+            max: 25,
+        });
+        sim.player_mut().gold = 150;
+        // interest on the 150 that were banked before the bonus came in
+        Waves::pay_wave_rewards(&mut sim, 1);
+        assert_eq!(sim.player().gold, 175);
+        // the bonus for every wave, interest only once and at most max
+        Waves::pay_wave_rewards(&mut sim, 2);
+        assert_eq!(sim.player().gold, 175 + 20 + 17);
+        sim.player_mut().gold = 1000;
+        Waves::pay_wave_rewards(&mut sim, 1);
+        assert_eq!(sim.player().gold, 1000 + 10 + 25);
+    }
+
+    #[test]
+    fn no_interest_without_settings() {
+        let mut sim = sim();
+        sim.settings.wave_clear_bonus = 0;
+        sim.settings.interest = None;
+        sim.player_mut().gold = 1000;
+        Waves::pay_wave_rewards(&mut sim, 1);
+        assert_eq!(sim.player().gold, 1000);
+    }
+
+    #[test]
+    fn clearing_a_wave_pays_once() {
+        let mut sim = sim();
+        sim.settings.wave_clear_bonus = 10;
+        sim.settings.interest = None;
+        sim.waves.waves.truncate(1);
+        sim.waves.waves[0].groups.truncate(1);
+        sim.waves.waves[0].groups[0].count = 1;
+        Waves::start_next(&mut sim);
+        Waves::tick(&mut sim);
+        let zombies = sim.enemies.enemies.keys().cloned().collect::<Vec<_>>();
+        assert_eq!(zombies.len(), 1);
+        sim.enemies.enemies.clear();
+        Waves::tick(&mut sim);
+        assert_eq!(sim.waves.status, WaveStatus::WaveFinished);
+        assert_eq!(sim.player().gold, 10);
+        Waves::tick(&mut sim);
+        assert_eq!(sim.player().gold, 10);
+    }
+
+    fn early_call_sim() -> Simulation {
+        let mut sim = sim();
+        sim.settings.early_call_reward = EarlyCallReward {
+            gold: 2.0,
+            mana: 1.0,
+        };
+        sim.player_mut().mana = 0.0;
+        return sim;
+    }
+
+    #[test]
+    fn calling_early_pays_for_the_time_left() {
+        let mut sim = early_call_sim();
+        let me = sim.me;
+        sim.waves.status = WaveStatus::Waiting(300);
+        Waves::call_next(&mut sim, me);
+        assert_eq!(sim.waves.status, WaveStatus::Waiting(0));
+        assert_eq!(sim.player().gold, 10);
+        assert_eq!(sim.player().mana, 5.0);
+        // nothing left on the countdown, nothing to earn
+        Waves::call_next(&mut sim, me);
+        assert_eq!(sim.player().gold, 10);
+    }
+
+    #[test]
+    fn overlapping_waves_pay_nothing() {
+        let mut sim = early_call_sim();
+        let me = sim.me;
+        Waves::start_next(&mut sim);
+        Waves::call_next(&mut sim, me);
+        assert_eq!(sim.waves.id, 2);
+        assert_eq!(sim.waves.active.len(), 2);
+        assert_eq!(sim.player().gold, 0);
+        assert_eq!(sim.player().mana, 0.0);
+        // and there is nothing to call after the last wave
+        sim.waves.id = sim.waves.waves.len();
+        assert!(!Waves::can_call_next(&sim));
+        Waves::call_next(&mut sim, me);
+        assert_eq!(sim.waves.active.len(), 2);
+    }
+
+    #[test]
+    fn legacy_waves_are_a_single_group() {
+        let wave: WaveSpec = from_str(
+            "(enemy: \"Zombie\", enemy_count: 10, spawn_delay: 20, spawns: [1], spawn_mode: Simultaneous)",
+        )
+        .unwrap();
+        assert_eq!(wave.groups.len(), 1);
+        let group = &wave.groups[0];
+        assert_eq!(group.enemy, "Zombie");
+        assert_eq!((group.count, group.spawn_delay, group.start), (10, 20, 0));
+        assert_eq!(group.spawns, vec![1]);
+        assert_eq!(group.spawn_mode, SpawnMode::Simultaneous);
+        assert_eq!((group.health, group.speed), (100, 100));
+        // everything but the enemy and its numbers is optional
+        let wave: WaveSpec =
+            from_str("(enemy: \"Brute\", enemy_count: 5, spawn_delay: 50)").unwrap();
+        assert!(wave.groups[0].spawns.is_empty());
+        assert_eq!(wave.groups[0].spawn_mode, SpawnMode::RoundRobin);
+    }
+
+    #[test]
+    fn group_waves_keep_their_groups() {
+        let wave: WaveSpec = from_str(
+            "(groups: [
+                (enemy: \"Runner\", count: 20, spawn_delay: 10),
+                (enemy: \"Knight\", count: 10, spawn_delay: 45, start: 240, health: 150),
+            ])",
+        )
+        .unwrap();
+        let groups: Vec<_> = wave
+            .groups
+            .iter()
+            .map(|g| (g.enemy.as_str(), g.count, g.start, g.health))
+            .collect();
+        assert_eq!(
+            groups,
+            vec![("Runner", 20, 0, 100), ("Knight", 10, 240, 150)]
+        );
+    }
+
+    #[test]
+    fn every_level_has_waves() {
+        for level in available_levels() {
+            let waves = Waves::new(&level);
+            assert!(!waves.waves.is_empty(), "{}", level);
+            for wave in waves.waves.iter() {
+                assert!(!wave.groups.is_empty(), "{}", level);
+                assert!(wave.groups.iter().all(|g| g.count > 0), "{}", level);
             }
         }
     }